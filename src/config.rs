@@ -0,0 +1,329 @@
+use anyhow::anyhow;
+use git2::Repository;
+use serde::Deserialize;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::LazyLock;
+use tabwriter::TabWriter;
+use tracing::*;
+
+/// Settings read from `~/.config/orpa/config.toml`.  These mirror the
+/// git-config keys, which take precedence.
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct FileConfig {
+    pub gitlab: GitlabSection,
+    pub orpa: OrpaSection,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct GitlabSection {
+    pub url: Option<String>,
+    pub token: Option<String>,
+    pub project_id: Option<u64>,
+    pub username: Option<String>,
+}
+
+#[derive(Deserialize, Default, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct OrpaSection {
+    pub notes_ref: Option<String>,
+    pub dedup: Option<bool>,
+    pub watchlist: Option<String>,
+}
+
+/// `$ORPA_CONFIG` if it's set; otherwise `orpa/config.toml` in the user's
+/// config directory
+pub fn config_path() -> Option<PathBuf> {
+    if let Some(path) = std::env::var_os("ORPA_CONFIG") {
+        return Some(path.into());
+    }
+    let dir = match std::env::var_os("XDG_CONFIG_HOME") {
+        Some(x) if !x.is_empty() => PathBuf::from(x),
+        _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+    };
+    Some(dir.join("orpa").join("config.toml"))
+}
+
+/// The contents of the config file.  A missing file is the same as an empty
+/// one; a malformed one is reported and then ignored.
+pub fn file_config() -> &'static FileConfig {
+    static FILE_CONFIG: LazyLock<FileConfig> = LazyLock::new(|| {
+        let Some(path) = config_path() else {
+            return FileConfig::default();
+        };
+        let txt = match std::fs::read_to_string(&path) {
+            Ok(x) => x,
+            Err(_) => return FileConfig::default(),
+        };
+        info!("Reading {}", path.display());
+        toml::from_str(&txt).unwrap_or_else(|e| {
+            warn!("{}: {}", path.display(), e);
+            FileConfig::default()
+        })
+    });
+    &FILE_CONFIG
+}
+
+/// What sort of value a git-config key takes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    String,
+    Integer,
+    /// "true" or "false", or any of git's other spellings
+    Bool,
+    /// `<host>:<project_id>`, possibly given several times
+    Project,
+}
+
+/// The git-config keys which orpa reads
+pub const KNOWN_KEYS: &[(&str, Kind, &str)] = &[
+    (
+        "gitlab.url",
+        Kind::String,
+        "The gitlab host, eg. gitlab.com",
+    ),
+    (
+        "gitlab.privateToken",
+        Kind::String,
+        "An API token for gitlab",
+    ),
+    (
+        "gitlab.projectId",
+        Kind::Integer,
+        "The numeric ID of the gitlab project",
+    ),
+    ("gitlab.username", Kind::String, "Your gitlab username"),
+    (
+        "orpa.project",
+        Kind::Project,
+        "Extra projects to track, as <host>:<project_id>",
+    ),
+    (
+        "orpa.watchlist",
+        Kind::String,
+        "Colon-separated globs of paths you care about",
+    ),
+    (
+        "orpa.noteTemplate",
+        Kind::String,
+        "The template for new notes",
+    ),
+    (
+        "orpa.nagTemplate",
+        Kind::String,
+        "The comment posted by `orpa nag`",
+    ),
+    (
+        "orpa.fetchTimeout",
+        Kind::Integer,
+        "Seconds to wait for each gitlab request",
+    ),
+    (
+        "orpa.closedMrWindow",
+        Kind::Integer,
+        "How many days back `orpa fetch --closed` looks",
+    ),
+    (
+        "orpa.httpProxy",
+        Kind::String,
+        "A proxy for gitlab requests (default: $HTTPS_PROXY)",
+    ),
+    (
+        "orpa.requireSignatures",
+        Kind::Bool,
+        "If true, commits without a good signature count as unreviewed",
+    ),
+    (
+        "orpa.sizeBuckets",
+        Kind::String,
+        "Upper bounds of the XS,S,M,L sizes for `orpa mr-size` (default: 50,200,500,1500)",
+    ),
+    (
+        "orpa.watchInterval",
+        Kind::Integer,
+        "Seconds between polls for `orpa branch --watch`",
+    ),
+    (
+        "orpa.autoMarkVerb",
+        Kind::String,
+        "The verb used by the post-commit hook",
+    ),
+];
+
+fn known_key(key: &str) -> Option<&'static (&'static str, Kind, &'static str)> {
+    // git-config keys are case-insensitive
+    KNOWN_KEYS.iter().find(|x| x.0.eq_ignore_ascii_case(key))
+}
+
+/// The list of known keys, for `orpa config --help`
+pub fn keys_help() -> bpaf::Doc {
+    let mut doc = bpaf::Doc::default();
+    doc.text("Settings:");
+    for (key, _, desc) in KNOWN_KEYS {
+        doc.text("\n  ");
+        doc.literal(key);
+        doc.text(&format!(": {}", desc));
+    }
+    doc
+}
+
+/// Hide secrets when printing values
+fn display_value(key: &str, value: &str) -> String {
+    if key.to_ascii_lowercase().ends_with("token") {
+        "***".into()
+    } else {
+        value.into()
+    }
+}
+
+/// Print all the orpa-related settings
+pub fn show_all(repo: &Repository) -> anyhow::Result<()> {
+    let config = repo.config()?.snapshot()?;
+    let mut tw = TabWriter::new(std::io::stdout());
+    for (key, kind, desc) in KNOWN_KEYS {
+        let values = match kind {
+            Kind::Project => multivar(&config, key)?,
+            _ => get_string(&config, key).into_iter().collect(),
+        };
+        if values.is_empty() {
+            writeln!(tw, "{}\t(not set)\t# {}", key, desc)?;
+        }
+        for value in values {
+            writeln!(tw, "{}\t{}\t# {}", key, display_value(key, &value), desc)?;
+        }
+    }
+    // Anything else which looks like it's for us
+    let mut entries = config.entries(Some("^(gitlab|orpa)\\."))?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        let key = entry.name().unwrap_or("");
+        if known_key(key).is_none() {
+            let value = entry.value().unwrap_or("");
+            writeln!(tw, "{}\t{}\t# (unknown)", key, display_value(key, value))?;
+        }
+    }
+    tw.flush()?;
+    Ok(())
+}
+
+/// Print the value of one key
+pub fn show(repo: &Repository, key: &str) -> anyhow::Result<()> {
+    let config = repo.config()?.snapshot()?;
+    let values = match known_key(key) {
+        Some((_, Kind::Project, _)) => multivar(&config, key)?,
+        _ => vec![get_string(&config, key)?],
+    };
+    for value in values {
+        println!("{}", display_value(key, &value));
+    }
+    Ok(())
+}
+
+/// Set a key in the repo's own config.  Keys which orpa knows about are
+/// checked first.
+pub fn set(repo: &Repository, key: &str, value: &str) -> anyhow::Result<()> {
+    let mut config = repo.config()?.open_level(git2::ConfigLevel::Local)?;
+    match known_key(key) {
+        None => {
+            warn!("{} isn't a key orpa knows about; setting it anyway", key);
+            config.set_str(key, value)?;
+        }
+        Some((_, Kind::String, _)) => config.set_str(key, value)?,
+        Some((_, Kind::Integer, _)) => {
+            let x = value
+                .parse::<i64>()
+                .map_err(|_| anyhow!("{}: expected an integer, got \"{}\"", key, value))?;
+            config.set_i64(key, x)?;
+        }
+        Some((_, Kind::Bool, _)) => {
+            let x = parse_bool(value)
+                .ok_or_else(|| anyhow!("{}: expected true or false, got \"{}\"", key, value))?;
+            config.set_bool(key, x)?;
+        }
+        Some((_, Kind::Project, _)) => {
+            let valid = value
+                .rsplit_once(':')
+                .is_some_and(|(host, id)| !host.is_empty() && id.parse::<u64>().is_ok());
+            if !valid {
+                return Err(anyhow!(
+                    "{}: expected <host>:<project_id>, got \"{}\"",
+                    key,
+                    value
+                ));
+            }
+            // Add it to the list, unless it's already there
+            config.set_multivar(key, &format!("^{}$", regex::escape(value)), value)?;
+        }
+    }
+    println!("{} = {}", key, display_value(key, value));
+    Ok(())
+}
+
+/// Remove a key (all of its values, if there are several) from the repo's
+/// own config
+pub fn unset(repo: &Repository, key: &str) -> anyhow::Result<()> {
+    let mut config = repo.config()?.open_level(git2::ConfigLevel::Local)?;
+    match config.remove_multivar(key, ".*") {
+        Ok(()) => println!("Unset {}", key),
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {
+            println!(
+                "{} isn't set in {}",
+                key,
+                repo.path().join("config").display()
+            )
+        }
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+/// The spellings which git-config accepts for booleans
+fn parse_bool(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" | "" => Some(false),
+        _ => None,
+    }
+}
+
+fn multivar(config: &git2::Config, key: &str) -> anyhow::Result<Vec<String>> {
+    let mut ret = vec![];
+    let mut entries = config.multivar(key, None)?;
+    while let Some(entry) = entries.next() {
+        ret.push(entry?.value().unwrap_or("").to_owned());
+    }
+    Ok(ret)
+}
+
+/// Look up a string setting, first in git-config and then in the config file
+pub fn get_string(config: &git2::Config, key: &str) -> anyhow::Result<String> {
+    if let Ok(x) = config.get_string(key) {
+        return Ok(x);
+    }
+    let file = file_config();
+    let value = match key {
+        "gitlab.url" => file.gitlab.url.clone(),
+        "gitlab.privateToken" => file.gitlab.token.clone(),
+        "gitlab.projectId" => file.gitlab.project_id.map(|x| x.to_string()),
+        "gitlab.username" => file.gitlab.username.clone(),
+        "orpa.watchlist" => file.orpa.watchlist.clone(),
+        _ => None,
+    };
+    value.ok_or_else(|| match config_path() {
+        Some(path) => anyhow!("{} is not set in git-config or {}", key, path.display()),
+        None => anyhow!("{} is not set", key),
+    })
+}
+
+/// Like `get_string`, but the value must be an integer
+pub fn get_i64(config: &git2::Config, key: &str) -> anyhow::Result<i64> {
+    if let Ok(x) = config.get_i64(key) {
+        return Ok(x);
+    }
+    let value = get_string(config, key)?;
+    value
+        .parse()
+        .map_err(|_| anyhow!("{}: expected an integer, got \"{}\"", key, value))
+}
@@ -0,0 +1,131 @@
+use crate::config;
+use crate::mr_db::MRWithVersions;
+use crate::review_db::notes_ref;
+use crate::{cached_mr_files, db_path, http_client, http_proxy};
+use anyhow::anyhow;
+use git2::Repository;
+use std::fs::File;
+use yansi::Paint;
+
+/// Check the things that orpa needs in order to work, and explain how to fix
+/// any that are missing.  Returns the number of failed checks.
+pub fn doctor(repo: &Repository) -> anyhow::Result<usize> {
+    let config = repo.config()?;
+    let url = config::get_string(&config, "gitlab.url").ok();
+    let token = config::get_string(&config, "gitlab.privateToken")
+        .ok()
+        .filter(|x| !x.is_empty());
+    let notes_ref = notes_ref().unwrap_or("refs/notes/commits");
+    let db_path = db_path(repo);
+    let proxy = http_proxy(&config);
+
+    match config::config_path() {
+        Some(path) if path.exists() => println!("Reading settings from {}", path.display()),
+        Some(path) => println!("{} doesn't exist; using git-config only", path.display()),
+        None => println!("No config file location; using git-config only"),
+    }
+
+    let mut n_failed = 0;
+    let mut check = |desc: &str, result: anyhow::Result<()>, fix: &str| match result {
+        Ok(()) => println!("{} {}", Paint::green("✓"), desc),
+        Err(e) => {
+            n_failed += 1;
+            println!("{} {}: {}", Paint::red("✗"), desc, e);
+            println!("    {}", fix);
+        }
+    };
+
+    check(
+        "gitlab.url is set",
+        url.as_ref().map(|_| ()).ok_or_else(|| anyhow!("not set")),
+        "Run `git config gitlab.url gitlab.example.com`",
+    );
+    check(
+        "gitlab.projectId is an integer",
+        config::get_i64(&config, "gitlab.projectId").map(|_| ()),
+        "Run `git config gitlab.projectId <id>`; the ID is shown on the project's gitlab page",
+    );
+    check(
+        "gitlab.privateToken is valid",
+        match &token {
+            None => Err(anyhow!("not set")),
+            // We'll have to assume it's valid
+            Some(_) if crate::OPTS.offline => Ok(()),
+            Some(token) => current_user(
+                url.as_deref().unwrap_or("gitlab.com"),
+                token,
+                proxy.as_deref(),
+            )
+            .map(|_| ()),
+        },
+        "Create an API token in gitlab, and run `git config gitlab.privateToken <token>`",
+    );
+    check(
+        "gitlab.username is set",
+        config::get_string(&config, "gitlab.username")
+            .map(|_| ())
+            .map_err(|_| anyhow!("not set")),
+        "Run `git config gitlab.username <your gitlab username>`",
+    );
+    check(
+        &format!("{} exists", notes_ref),
+        repo.find_reference(notes_ref)
+            .map(|_| ())
+            .map_err(|_| anyhow!("not found")),
+        "Review something with `orpa mark`, or fetch your notes from a remote",
+    );
+    check(
+        &format!("{} is writable", db_path.display()),
+        check_writable(&db_path),
+        "Run `orpa fetch` to create it, or check its permissions",
+    );
+    check(
+        "The MR cache is readable",
+        check_mrs(repo),
+        "Run `orpa fetch` to refresh the cache",
+    );
+    Ok(n_failed)
+}
+
+/// Check the token by asking gitlab who it belongs to.  Returns their
+/// username.
+pub fn current_user(host: &str, token: &str, proxy: Option<&str>) -> anyhow::Result<String> {
+    #[derive(serde::Deserialize)]
+    struct User {
+        username: String,
+    }
+    let client = http_client(std::time::Duration::from_secs(30), proxy)?;
+    let user: User = client
+        .get(format!("https://{}/api/v4/user", host))
+        .header("PRIVATE-TOKEN", token)
+        .send()?
+        .error_for_status()?
+        .json()?;
+    Ok(user.username)
+}
+
+fn check_writable(path: &std::path::Path) -> anyhow::Result<()> {
+    if !path.is_dir() {
+        return Err(anyhow!("not found"));
+    }
+    let probe = path.join(".orpa-doctor");
+    File::create(&probe)?;
+    std::fs::remove_file(&probe)?;
+    Ok(())
+}
+
+fn check_mrs(repo: &Repository) -> anyhow::Result<()> {
+    let mut n_valid = 0;
+    let mut n_invalid = 0;
+    for path in cached_mr_files(repo).map_err(|_| anyhow!("no MRs cached"))? {
+        match serde_json::from_reader::<_, MRWithVersions>(File::open(path)?) {
+            Ok(_) => n_valid += 1,
+            Err(_) => n_invalid += 1,
+        }
+    }
+    match (n_valid, n_invalid) {
+        (0, 0) => Err(anyhow!("no MRs cached")),
+        (_, 0) => Ok(()),
+        (_, n) => Err(anyhow!("{} of {} MR files are invalid", n, n_valid + n)),
+    }
+}
@@ -0,0 +1,107 @@
+use crate::mr_db::MRWithVersions;
+use crate::review_db::*;
+use crate::{cached_mrs, mr_dir};
+use anyhow::anyhow;
+use git2::Repository;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::path::Path;
+
+const SCHEMA_VERSION: u32 = 1;
+
+/// Everything needed to reproduce someone's review state in another clone
+#[derive(Serialize, Deserialize)]
+struct Export {
+    schema_version: u32,
+    notes: Vec<ExportedNote>,
+    merge_requests: Vec<MRWithVersions>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct ExportedNote {
+    oid: String,
+    note: String,
+}
+
+pub fn export(repo: &Repository, output: &Path) -> anyhow::Result<()> {
+    let mut notes = vec![];
+    for (_, oid) in all_notes(repo)? {
+        if let Some(note) = get_note(repo, oid)? {
+            notes.push(ExportedNote {
+                oid: oid.to_string(),
+                note,
+            });
+        }
+    }
+    // If we've never fetched, there are no MRs
+    let merge_requests = cached_mrs(repo).unwrap_or_default();
+    let export = Export {
+        schema_version: SCHEMA_VERSION,
+        notes,
+        merge_requests,
+    };
+    serde_json::to_writer(File::create(output)?, &export)?;
+    println!(
+        "Exported {} notes and {} MRs to {}",
+        export.notes.len(),
+        export.merge_requests.len(),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Load an export into this repo.  Notes are merged line-by-line with any
+/// existing notes, so importing the same file twice does nothing the second
+/// time.  Cached MRs are replaced unless ours is more recent.
+pub fn import(repo: &Repository, input: &Path) -> anyhow::Result<()> {
+    let export: Export = serde_json::from_reader(File::open(input)?)?;
+    if export.schema_version != SCHEMA_VERSION {
+        return Err(anyhow!(
+            "{}: Unsupported schema version {} (expected {})",
+            input.display(),
+            export.schema_version,
+            SCHEMA_VERSION
+        ));
+    }
+
+    let sig = repo.signature()?;
+    let mut n_notes = 0;
+    for x in &export.notes {
+        let oid = git2::Oid::from_str(&x.oid)?;
+        let old_note = get_note(repo, oid)?.unwrap_or_default();
+        let mut lines = old_note.lines().collect::<Vec<_>>();
+        for line in x.note.lines() {
+            if !lines.contains(&line) {
+                lines.push(line);
+            }
+        }
+        let new_note = lines.join("\n");
+        if new_note != old_note {
+            repo.note(&sig, &sig, notes_ref(), oid, &new_note, true)?;
+            n_notes += 1;
+        }
+    }
+    invalidate_reviews(repo);
+
+    let mut n_mrs = 0;
+    for mr in &export.merge_requests {
+        let dir = mr_dir(repo, mr.mr.project_id)?;
+        std::fs::create_dir_all(&dir)?;
+        let path = dir.join(mr.mr.iid.0.to_string());
+        if let Ok(f) = File::open(&path) {
+            let ours: MRWithVersions = serde_json::from_reader(f)?;
+            if ours.mr.updated_at >= mr.mr.updated_at {
+                continue;
+            }
+        }
+        serde_json::to_writer(File::create(&path)?, mr)?;
+        n_mrs += 1;
+    }
+    println!(
+        "Imported {} notes and {} MRs from {}",
+        n_notes,
+        n_mrs,
+        input.display()
+    );
+    Ok(())
+}
@@ -1,11 +1,14 @@
-use crate::{db_path, mr_db::MRWithVersions, GitlabConfig, Version, VersionInfo};
+use crate::{mr_db::MRWithVersions, GitlabConfig, Version, VersionInfo};
 use anyhow::anyhow;
 use chrono::{DateTime, Utc};
 use git2::{Oid, Repository};
-use gitlab::Gitlab;
+use indicatif::{MultiProgress, ProgressBar, ProgressDrawTarget, ProgressStyle};
+use rayon::prelude::*;
 use serde::{Deserialize, Serialize};
 use std::collections::{BTreeMap, HashSet};
+use std::convert::TryFrom;
 use std::fs::File;
+use std::io::IsTerminal;
 use tracing::*;
 
 #[derive(Serialize, Deserialize, Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy, Hash)]
@@ -56,6 +59,8 @@ pub struct MergeRequest {
     pub draft: bool,
     pub state: MergeRequestState,
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
     pub target_branch: String,
     pub source_branch: String,
     pub author: UserBasic,
@@ -64,15 +69,27 @@ pub struct MergeRequest {
     pub reviewers: Option<Vec<UserBasic>>,
     pub sha: Option<ObjectId>,
     pub diff_refs: Option<DiffRefs>,
-    // Also: created_at, merged_at, closed_at, merged_by, closed_by,
+    #[serde(default)]
+    pub labels: Vec<String>,
+    /// Whether gitlab thinks the MR conflicts with its target branch
+    #[serde(default)]
+    pub has_conflicts: Option<bool>,
+    /// The most recent pipeline.  Gitlab doesn't include this in the list of
+    /// MRs, so `fetch` queries for it separately.
+    #[serde(default)]
+    pub pipeline: Option<Pipeline>,
+    /// Who's reviewing this MR, as set by `orpa mr-tracker`.  Gitlab doesn't
+    /// know about this, so `fetch` carries it over from the cache.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reviewer_claim: Option<String>,
+    // Also: merged_at, closed_at, merged_by, closed_by,
     // upvotes, downvotes, source_project_id, target_project_id,
-    // labels, allow_collaboration, allow_maintainer_to_push, milestone,
+    // allow_collaboration, allow_maintainer_to_push, milestone,
     // squash, merge_when_pipeline_succeeds, merge_status, merge_error,
     // rebase_in_progress, merge_commit_sha, squash_commit_sha, subscribed,
     // time_stats, blocking_discussions_resolved, changes_count,
     // user_notes_count, discussion_locked, should_remove_source_branch,
-    // force_remove_source_branch, has_conflicts, user, web_url, pipeline,
-    // first_contribution
+    // force_remove_source_branch, user, web_url, first_contribution
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -82,23 +99,281 @@ pub struct UserBasic {
     // Also: id, state, avatar_url, web_url
 }
 
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Pipeline {
+    pub id: u64,
+    pub status: String,
+    pub web_url: String,
+    #[serde(default)]
+    pub sha: Option<ObjectId>,
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
+    // Also: iid, project_id, ref, source, updated_at
+}
+
+/// The approval state of an MR.  This isn't cached; it's queried on demand.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Approvals {
+    pub approved: bool,
+    #[serde(default)]
+    pub approved_by: Vec<Approver>,
+    /// Only reported by gitlab EE
+    #[serde(default)]
+    pub approvers_left: Vec<UserBasic>,
+    // Also: approvals_required, approvals_left, user_has_approved,
+    // user_can_approve
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Approver {
+    pub user: UserBasic,
+}
+
+/// A comment on an MR.  Like approvals, these are queried on demand.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Comment {
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    // Also: id, author, system, updated_at, resolvable
+}
+
+/// A discussion thread on an MR.  Also queried on demand.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Discussion {
+    pub id: String,
+    pub notes: Vec<Note>,
+    // Also: individual_note
+}
+
+impl Discussion {
+    /// Gitlab only tracks resolution per-note, so a thread is resolved when
+    /// all of its resolvable notes are
+    pub fn resolved(&self) -> bool {
+        self.notes.iter().all(|x| !x.resolvable || x.resolved)
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct Note {
+    pub author: UserBasic,
+    pub body: String,
+    pub created_at: DateTime<Utc>,
+    /// Notes generated by gitlab itself, like "added 1 commit"
+    #[serde(default)]
+    pub system: bool,
+    #[serde(default)]
+    pub resolvable: bool,
+    #[serde(default)]
+    pub resolved: bool,
+    /// Where in the diff the note was left, if it's an inline comment
+    #[serde(default)]
+    pub position: Option<InlinePosition>,
+    // Also: id, type, updated_at, noteable_id, noteable_type, resolved_by
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct InlinePosition {
+    pub new_path: Option<String>,
+    pub new_line: Option<u64>,
+    pub old_path: Option<String>,
+    pub old_line: Option<u64>,
+    // Also: base_sha, start_sha, head_sha, position_type, line_range
+}
+
+/// A change in an MR's state, eg. being merged
+#[derive(Deserialize, Debug, Clone)]
+pub struct StateEvent {
+    pub created_at: DateTime<Utc>,
+    /// "opened", "closed", "reopened", "merged", or "locked"
+    pub state: String,
+    #[serde(default)]
+    pub user: Option<UserBasic>,
+    // Also: id, resource_type, resource_id
+}
+
 #[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
 pub struct DiffRefs {
     pub base_sha: Option<ObjectId>,
     // Also: head_sha, start_sha
 }
 
-pub fn fetch(repo: &Repository) -> anyhow::Result<()> {
-    let config = GitlabConfig::load(repo)?;
+/// A client for the gitlab crate's API types.  The gitlab crate's own
+/// client can't be given a timeout or a proxy, so this one sends its
+/// requests through `GitlabConfig::http_client` instead.
+pub struct Gitlab {
+    client: reqwest::blocking::Client,
+    rest_url: url::Url,
+    token: String,
+}
+
+impl gitlab::api::RestClient for Gitlab {
+    type Error = gitlab::RestError;
+
+    fn rest_endpoint(
+        &self,
+        endpoint: &str,
+    ) -> Result<url::Url, gitlab::api::ApiError<Self::Error>> {
+        debug!("REST api call {}", endpoint);
+        Ok(self.rest_url.join(endpoint)?)
+    }
+}
 
-    let db_path = db_path(repo);
-    let mr_dir = db_path.join("merge_requests");
+impl gitlab::api::Client for Gitlab {
+    fn rest(
+        &self,
+        mut request: http::request::Builder,
+        body: Vec<u8>,
+    ) -> Result<http::Response<bytes::Bytes>, gitlab::api::ApiError<Self::Error>> {
+        let call = || -> Result<_, gitlab::RestError> {
+            let headers = request.headers_mut().unwrap();
+            let token = http::HeaderValue::from_str(&self.token).map_err(http::Error::from)?;
+            headers.insert("PRIVATE-TOKEN", token);
+            let request = reqwest::blocking::Request::try_from(request.body(body)?)?;
+            let resp = self.client.execute(request)?;
+            let mut ret = http::Response::builder()
+                .status(resp.status())
+                .version(resp.version());
+            let headers = ret.headers_mut().unwrap();
+            for (key, value) in resp.headers() {
+                headers.insert(key, value.clone());
+            }
+            Ok(ret.body(resp.bytes()?)?)
+        };
+        call().map_err(gitlab::api::ApiError::client)
+    }
+}
 
+pub fn connect(config: &GitlabConfig) -> anyhow::Result<Gitlab> {
+    if crate::OPTS.offline {
+        return Err(anyhow!("Can't connect to gitlab in offline mode"));
+    }
     info!("Connecting to gitlab at {}", config.host);
-    let gl = Gitlab::new(&config.host, &config.token)?;
+    Ok(Gitlab {
+        client: config.http_client()?,
+        rest_url: url::Url::parse(&format!("https://{}/api/v4/", config.host))?,
+        token: config.token.clone(),
+    })
+}
+
+/// Leave a comment on an MR
+pub fn post_comment(
+    gl: &Gitlab,
+    config: &GitlabConfig,
+    mr: MergeRequestInternalId,
+    body: &str,
+) -> anyhow::Result<()> {
+    use gitlab::api::{ignore, projects::merge_requests::notes::CreateMergeRequestNote, Query};
+    let query = CreateMergeRequestNote::builder()
+        .project(config.project_id.0)
+        .merge_request(mr.0)
+        .body(body)
+        .build()
+        .map_err(|e| anyhow!(e))?;
+    ignore(query).query(gl)?;
+    Ok(())
+}
 
-    println!("Fetching open MRs for project {}...", config.project_id.0);
-    let mrs: Vec<MergeRequest> = {
+/// Add a user to the MR's reviewers or assignees.  The existing ones are
+/// kept.  Returns the user's full name and the updated MR.
+pub fn add_to_mr(
+    gl: &Gitlab,
+    config: &GitlabConfig,
+    mr: MergeRequestInternalId,
+    username: &str,
+    role: crate::AssignRole,
+) -> anyhow::Result<(String, MergeRequest)> {
+    use gitlab::api::{
+        projects::merge_requests::{EditMergeRequest, MergeRequest as GetMergeRequest},
+        users::Users,
+        Query,
+    };
+    #[derive(Deserialize)]
+    struct User {
+        id: u64,
+        name: String,
+    }
+    #[derive(Deserialize)]
+    struct Current {
+        #[serde(default)]
+        assignees: Vec<User>,
+        #[serde(default)]
+        reviewers: Vec<User>,
+    }
+
+    let users: Vec<User> = Users::builder()
+        .username(username)
+        .build()
+        .map_err(|e| anyhow!(e))?
+        .query(gl)?;
+    let user = users
+        .into_iter()
+        .next()
+        .ok_or_else(|| anyhow!("{}: No such gitlab user", username))?;
+    // Setting the reviewers replaces them, so we need the current ones
+    let current: Current = GetMergeRequest::builder()
+        .project(config.project_id.0)
+        .merge_request(mr.0)
+        .build()
+        .map_err(|e| anyhow!(e))?
+        .query(gl)?;
+    let mut edit = EditMergeRequest::builder();
+    edit.project(config.project_id.0).merge_request(mr.0);
+    let ids = |xs: Vec<User>| {
+        xs.into_iter()
+            .map(|x| x.id)
+            .chain(std::iter::once(user.id))
+            .collect::<HashSet<_>>()
+    };
+    match role {
+        crate::AssignRole::Reviewer => edit.reviewers(ids(current.reviewers).into_iter()),
+        crate::AssignRole::Assignee => edit.assignees(ids(current.assignees).into_iter()),
+    };
+    let updated: MergeRequest = edit.build().map_err(|e| anyhow!(e))?.query(gl)?;
+    Ok((user.name, updated))
+}
+
+pub fn fetch(
+    repo: &Repository,
+    no_parallel: bool,
+    project: Option<u64>,
+    closed: bool,
+) -> anyhow::Result<()> {
+    let configs = GitlabConfig::load_all(repo)?;
+    // Only look at MRs which were closed recently; there could be thousands
+    // of older ones
+    let closed_since = if closed {
+        let days = repo.config()?.get_i64("orpa.closedMrWindow").unwrap_or(90);
+        Some(Utc::now() - chrono::Duration::days(days))
+    } else {
+        None
+    };
+    if let Some(project) = project {
+        if !configs.iter().any(|x| x.project_id.0 == project) {
+            return Err(anyhow!("Project {} isn't configured", project));
+        }
+    }
+    for config in &configs {
+        if project.is_none_or(|x| x == config.project_id.0) {
+            fetch_project(repo, config, no_parallel, closed_since)?;
+        }
+    }
+    Ok(())
+}
+
+fn fetch_project(
+    repo: &Repository,
+    config: &GitlabConfig,
+    no_parallel: bool,
+    closed_since: Option<DateTime<Utc>>,
+) -> anyhow::Result<()> {
+    let mr_dir = &config.mr_dir;
+    let gl = connect(config)?;
+
+    println!(
+        "Fetching open MRs for project {} from {}...",
+        config.project_id.0, config.host
+    );
+    let mut mrs: Vec<MergeRequest> = {
         use gitlab::api::{projects::merge_requests::*, *};
         let query = MergeRequestsBuilder::default()
             .project(config.project_id.0)
@@ -107,25 +382,80 @@ pub fn fetch(repo: &Repository) -> anyhow::Result<()> {
             .map_err(|e| anyhow!(e))?;
         paged(query, Pagination::All).query(&gl)?
     };
+    if let Some(since) = closed_since {
+        println!("Fetching MRs closed since {}...", since.date_naive());
+        let closed: Vec<MergeRequest> = {
+            use gitlab::api::{projects::merge_requests::*, *};
+            let mut ret = vec![];
+            for state in [MergeRequestState::Merged, MergeRequestState::Closed] {
+                let query = MergeRequestsBuilder::default()
+                    .project(config.project_id.0)
+                    .state(state)
+                    .updated_after(since)
+                    .build()
+                    .map_err(|e| anyhow!(e))?;
+                ret.extend(paged(query, Pagination::All).query(&gl)?);
+            }
+            ret
+        };
+        mrs.extend(closed);
+    }
 
     info!("Updating the DB with new versions");
-    std::fs::create_dir_all(&mr_dir)?;
-    let client = reqwest::blocking::Client::new();
-    for mr in &mrs {
+    std::fs::create_dir_all(mr_dir)?;
+    let client = config.http_client()?;
+    let progress = MultiProgress::with_draw_target(if std::io::stderr().is_terminal() {
+        ProgressDrawTarget::stderr()
+    } else {
+        ProgressDrawTarget::hidden()
+    });
+    let bar = progress.add(ProgressBar::new(mrs.len() as u64));
+    bar.set_style(ProgressStyle::with_template(
+        "[{pos}/{len}] {bar:40} {msg}",
+    )?);
+    // Loading the version history from gitlab is slow, so we do it for
+    // all MRs in parallel.  `Repository` isn't `Sync`, so everything which
+    // touches the repo happens afterwards, one MR at a time.
+    let load = |mr: &MergeRequest| {
         let _s = tracing::info_span!("", mr = mr.iid.0).entered();
+        bar.set_message(format!("Fetching !{}...", mr.iid.0));
         let path = mr_dir.join(mr.iid.0.to_string());
-        let mut versions = match std::fs::read_to_string(&path) {
-            Ok(txt) => serde_json::from_str::<MRWithVersions>(&txt)?.versions,
-            Err(_) => BTreeMap::default(),
+        let (versions, cached) = match std::fs::read_to_string(&path) {
+            Ok(txt) => {
+                let cached = serde_json::from_str::<MRWithVersions>(&txt)?;
+                (cached.versions, Some(cached.mr))
+            }
+            Err(_) => (BTreeMap::default(), None),
         };
-        if let Err(e) = update_versions(mr, &mut versions, &client, &config, repo, &gl) {
-            error!("{e}");
-        }
+        let fetched = fetch_versions(mr, &versions, &client, config, &progress);
+        let pipeline = refresh_pipeline(&client, config, mr, cached.as_ref());
+        let reviewer_claim = cached.and_then(|x| x.reviewer_claim);
+        bar.inc(1);
+        anyhow::Ok((versions, reviewer_claim, fetched, pipeline))
+    };
+    let loaded = if no_parallel {
+        mrs.iter().map(load).collect::<Vec<_>>()
+    } else {
+        mrs.par_iter().map(load).collect::<Vec<_>>()
+    };
+    bar.finish_and_clear();
 
+    for (mr, x) in mrs.iter().zip(loaded) {
+        let (mut versions, reviewer_claim, fetched, pipeline) = x?;
+        let _s = tracing::info_span!("", mr = mr.iid.0).entered();
+        if let Some(fetched) = fetched {
+            if let Err(e) = apply_versions(mr, &mut versions, fetched, config, repo, &gl) {
+                error!("{e}");
+            }
+        }
         serde_json::to_writer(
-            File::create(path)?,
+            File::create(mr_dir.join(mr.iid.0.to_string()))?,
             &MRWithVersions {
-                mr: mr.clone(),
+                mr: MergeRequest {
+                    pipeline,
+                    reviewer_claim,
+                    ..mr.clone()
+                },
                 versions,
             },
         )?;
@@ -135,6 +465,10 @@ pub fn fetch(repo: &Repository) -> anyhow::Result<()> {
     let mrs: HashSet<MergeRequestInternalId> = mrs.into_iter().map(|mr| mr.iid).collect();
     for entry in std::fs::read_dir(mr_dir)? {
         let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            // Belongs to another project
+            continue;
+        }
         let id = MergeRequestInternalId(entry.file_name().into_string().unwrap().parse()?);
         if mrs.contains(&id) {
             // We already saw this one, it's still open
@@ -174,13 +508,24 @@ pub fn fetch(repo: &Repository) -> anyhow::Result<()> {
             mr.iid.0,
             crate::fmt_state(new_info.state)
         );
-        if let Err(e) = update_versions(&new_info, &mut versions, &client, &config, repo, &gl) {
+        if let Err(e) = update_versions(
+            &new_info,
+            &mut versions,
+            &client,
+            config,
+            repo,
+            &gl,
+            &progress,
+        ) {
             error!("{e}");
         }
         serde_json::to_writer(
             File::create(entry.path())?,
             &MRWithVersions {
-                mr: new_info,
+                mr: MergeRequest {
+                    reviewer_claim: mr.reviewer_claim,
+                    ..new_info
+                },
                 versions,
             },
         )?;
@@ -189,6 +534,94 @@ pub fn fetch(repo: &Repository) -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Sync a single MR which is already in the cache, without listing all the
+/// open MRs first
+pub fn fetch_single(repo: &Repository, target: &str) -> anyhow::Result<()> {
+    let MRWithVersions { mr, mut versions } = crate::cached_mr(repo, target)?;
+    let config = GitlabConfig::for_project(repo, mr.project_id)?;
+    let gl = connect(&config)?;
+    let client = config.http_client()?;
+    let _s = tracing::info_span!("", mr = mr.iid.0).entered();
+
+    let new_info: MergeRequest =
+        get_json(&client, &config, &format!("merge_requests/{}", mr.iid.0))?;
+    let pipeline = refresh_pipeline(&client, &config, &new_info, Some(&mr));
+    let progress = MultiProgress::with_draw_target(if std::io::stderr().is_terminal() {
+        ProgressDrawTarget::stderr()
+    } else {
+        ProgressDrawTarget::hidden()
+    });
+    let n_versions = versions.len();
+    update_versions(
+        &new_info,
+        &mut versions,
+        &client,
+        &config,
+        repo,
+        &gl,
+        &progress,
+    )?;
+
+    let mr_ref = crate::fmt_mr_ref(repo, &new_info);
+    if new_info.state != mr.state {
+        println!(
+            "Status of {} changed to {}",
+            mr_ref,
+            crate::fmt_state(new_info.state)
+        );
+    }
+    if versions.len() == n_versions {
+        println!("{} has no new versions", mr_ref);
+    }
+    serde_json::to_writer(
+        File::create(config.mr_dir.join(mr.iid.0.to_string()))?,
+        &MRWithVersions {
+            mr: MergeRequest {
+                pipeline,
+                reviewer_claim: mr.reviewer_claim,
+                ..new_info
+            },
+            versions,
+        },
+    )?;
+    Ok(())
+}
+
+/// Fetch the notes from every remote, and merge them into our notes ref.
+/// The remote notes are stored under "refs/notes/remote/<remote>/".
+pub fn merge_notes(repo: &Repository) -> anyhow::Result<()> {
+    let local_ref = crate::review_db::notes_ref().unwrap_or("refs/notes/commits");
+    let name = local_ref.trim_start_matches("refs/notes/");
+    let git = || {
+        let mut cmd = std::process::Command::new("git");
+        cmd.arg("--git-dir").arg(repo.path());
+        cmd
+    };
+    for remote in repo.remotes()?.iter().flatten() {
+        println!("Fetching notes from {}...", remote);
+        let refspec = format!("+refs/notes/*:refs/notes/remote/{}/*", remote);
+        let status = git().args(["fetch", remote, &refspec]).status()?;
+        if !status.success() {
+            error!("Couldn't fetch notes from {}: git fetch {}", remote, status);
+            continue;
+        }
+        let remote_ref = format!("refs/notes/remote/{}/{}", remote, name);
+        if repo.find_reference(&remote_ref).is_err() {
+            info!("{} has no {}", remote, local_ref);
+            continue;
+        }
+        let status = git()
+            .args(["notes", "--ref", local_ref, "merge", "--strategy=union"])
+            .arg(&remote_ref)
+            .status()?;
+        if !status.success() {
+            error!("Couldn't merge {}: git notes merge {}", remote_ref, status);
+        }
+    }
+    crate::review_db::invalidate_reviews(repo);
+    Ok(())
+}
+
 fn update_versions(
     mr: &MergeRequest,
     versions: &mut BTreeMap<Version, VersionInfo>,
@@ -196,39 +629,84 @@ fn update_versions(
     config: &GitlabConfig,
     repo: &Repository,
     gl: &Gitlab,
+    progress: &MultiProgress,
 ) -> anyhow::Result<()> {
-    let mr_iid = mr.iid.0;
+    match fetch_versions(mr, versions, client, config, progress) {
+        Some(fetched) => apply_versions(mr, versions, fetched, config, repo, gl),
+        None => Ok(()),
+    }
+}
+
+/// Query gitlab for any new versions of the MR.  Returns `None` if the MR
+/// hasn't changed since we last saw it.
+fn fetch_versions(
+    mr: &MergeRequest,
+    versions: &BTreeMap<Version, VersionInfo>,
+    client: &reqwest::blocking::Client,
+    config: &GitlabConfig,
+    progress: &MultiProgress,
+) -> Option<anyhow::Result<Vec<(Version, VersionInfo)>>> {
     let latest = versions.last_key_value();
     // We only update the DB if the head has changed.  Technically we
     // should re-check the base each time as well (in case the target
     // branch has changed); however, this means making an API request
-    // per-MR, and is slow.
+    // per-MR, and is slow.  `orpa mr-base-change` does it on demand.
     let current_head = mr.sha.as_ref().unwrap();
     if latest.as_ref().map(|x| &x.1.head) == Some(current_head) {
         info!("Skipping MR since its head rev hasn't changed");
-        return Ok(());
+        return None;
     }
-    let recent_versions = match query_versions(client, config, mr.iid, versions) {
+    let spinner = progress.add(ProgressBar::new_spinner());
+    spinner.set_message(format!("Querying versions of !{}...", mr.iid.0));
+    spinner.enable_steady_tick(std::time::Duration::from_millis(100));
+    let resp = query_versions(client, config, mr.iid, versions);
+    spinner.finish_and_clear();
+    Some(resp)
+}
+
+/// Record the versions we got from `fetch_versions` in the DB, and create
+/// refs for them.
+fn apply_versions(
+    mr: &MergeRequest,
+    versions: &mut BTreeMap<Version, VersionInfo>,
+    fetched: anyhow::Result<Vec<(Version, VersionInfo)>>,
+    config: &GitlabConfig,
+    repo: &Repository,
+    gl: &Gitlab,
+) -> anyhow::Result<()> {
+    let mr_iid = mr.iid.0;
+    let current_head = mr.sha.as_ref().unwrap();
+    let mut recent_versions = match fetched {
         Ok(x) => x,
         Err(e) => {
             error!("Couldn't query the version history: {e}");
             info!("Falling back to recording the current state as the lastest version");
-            let version = latest.map_or(Version(0), |x| Version(x.0 .0 + 1));
+            let latest = versions.last_key_value();
+            let version = match latest {
+                Some((x, _)) => x.plus(1)?,
+                None => Version(0),
+            };
             let info = VersionInfo {
                 base: mr_base(repo, gl, config.project_id, mr, current_head.as_oid())?,
                 head: current_head.clone(),
+                created_at: None,
             };
             vec![(version, info)]
         }
     };
-    for (version, info) in &recent_versions {
-        let prev = versions.insert(*version, info.clone());
-        if let Some(prev) = &prev {
+    for (version, info) in &mut recent_versions {
+        if let Some(prev) = versions.get(version) {
+            // Keep the time we first saw it
+            info.created_at = prev.created_at;
             if prev != info {
                 warn!("Changed existing version! Was {prev}, now {info}");
             }
         } else {
-            let ref_name = format!("refs/orpa/{}_{}/{}", mr_iid, mr.source_branch, version);
+            info.created_at = Some(Utc::now());
+            let ref_name = format!(
+                "refs/orpa/{}/{}_{}/{}",
+                mr.project_id.0, mr_iid, mr.source_branch, version
+            );
             let reflog_msg = format!("orpa: creating ref for !{} {}", mr_iid, version);
             match repo.reference(&ref_name, info.head.as_oid(), false, &reflog_msg) {
                 Ok(_) => info!("Created ref {ref_name}"),
@@ -236,6 +714,7 @@ fn update_versions(
             }
             println!("Inserted {info}");
         }
+        versions.insert(*version, info.clone());
     }
     if let Some((version, _)) = recent_versions.last() {
         println!("Updated !{mr_iid} to {}", version);
@@ -284,6 +763,293 @@ fn mr_base<'a>(
     }
 }
 
+/// Re-compute the merge base of the latest version of each open MR (or
+/// just the given one) against the current head of its target branch, and
+/// update the cache if it's moved.
+pub fn check_bases(repo: &Repository, target: Option<&str>) -> anyhow::Result<()> {
+    let mrs = match target {
+        Some(x) => vec![crate::cached_mr(repo, x)?],
+        None => crate::cached_open_mrs(repo)?,
+    };
+    let mut n_changed = 0;
+    for MRWithVersions { mr, mut versions } in mrs {
+        let _s = tracing::info_span!("", mr = mr.iid.0).entered();
+        let mr_ref = crate::fmt_mr_ref(repo, &mr);
+        let Some(info) = versions.values_mut().last() else {
+            continue;
+        };
+        let config = GitlabConfig::for_project(repo, mr.project_id)?;
+        let client = config.http_client()?;
+        let target_head = match query_branch_head(&client, &config, &mr.target_branch) {
+            Ok(x) => x,
+            Err(e) => {
+                error!(
+                    "{}: Couldn't get the head of {}: {}",
+                    mr_ref, mr.target_branch, e
+                );
+                continue;
+            }
+        };
+        let base: ObjectId = match repo.merge_base(info.head.as_oid(), target_head) {
+            Ok(x) => x.into(),
+            Err(e) => {
+                error!("{}: {} (try running git fetch)", mr_ref, e);
+                continue;
+            }
+        };
+        if base == info.base {
+            info!("Base hasn't changed");
+            continue;
+        }
+        println!(
+            "{}: base changed from {} to {}",
+            mr_ref,
+            &info.base.0[..10],
+            &base.0[..10]
+        );
+        info.base = base;
+        n_changed += 1;
+        let path = config.mr_dir.join(mr.iid.0.to_string());
+        serde_json::to_writer(File::create(path)?, &MRWithVersions { mr, versions })?;
+    }
+    if n_changed == 0 {
+        println!("No merge bases have changed");
+    }
+    Ok(())
+}
+
+/// The commit at the tip of the given branch, according to gitlab
+fn query_branch_head(
+    client: &reqwest::blocking::Client,
+    config: &GitlabConfig,
+    branch: &str,
+) -> anyhow::Result<Oid> {
+    #[derive(Deserialize)]
+    struct RepoBranch {
+        commit: RepoCommit,
+    }
+    #[derive(Deserialize)]
+    struct RepoCommit {
+        id: ObjectId,
+    }
+    let resp: RepoBranch = get_json(
+        client,
+        config,
+        &format!("repository/branches/{}", branch.replace('/', "%2F")),
+    )?;
+    Ok(resp.commit.id.as_oid())
+}
+
+/// GET a path under the project's API endpoint and parse the JSON response
+fn get_json<T: serde::de::DeserializeOwned>(
+    client: &reqwest::blocking::Client,
+    config: &GitlabConfig,
+    path: &str,
+) -> anyhow::Result<T> {
+    Ok(get(client, config, path)?.json()?)
+}
+
+/// GET every page of a list under the project's API endpoint, following
+/// gitlab's `x-next-page` header
+fn get_all_pages<T: serde::de::DeserializeOwned>(
+    client: &reqwest::blocking::Client,
+    config: &GitlabConfig,
+    path: &str,
+) -> anyhow::Result<Vec<T>> {
+    let sep = if path.contains('?') { '&' } else { '?' };
+    let mut ret = vec![];
+    let mut page = "1".to_owned();
+    loop {
+        let resp = get(client, config, &format!("{path}{sep}page={page}"))?;
+        let next = resp
+            .headers()
+            .get("x-next-page")
+            .and_then(|x| x.to_str().ok())
+            .filter(|x| !x.is_empty())
+            .map(str::to_owned);
+        ret.extend(resp.json::<Vec<T>>()?);
+        match next {
+            Some(x) => page = x,
+            None => return Ok(ret),
+        }
+    }
+}
+
+/// GET a path under the project's API endpoint.  If gitlab says we're making
+/// too many requests, we wait a bit and try again.
+fn get(
+    client: &reqwest::blocking::Client,
+    config: &GitlabConfig,
+    path: &str,
+) -> anyhow::Result<reqwest::blocking::Response> {
+    const MAX_RETRIES: u32 = 5;
+    if crate::OPTS.offline {
+        return Err(anyhow!("Can't query gitlab in offline mode"));
+    }
+    let url = format!(
+        "https://{}/api/v4/projects/{}/{}",
+        config.host, config.project_id.0, path,
+    );
+    for attempt in 0.. {
+        let resp = client
+            .get(&url)
+            .header("PRIVATE-TOKEN", &config.token)
+            .send()?;
+        if resp.status() != reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Ok(resp.error_for_status()?);
+        }
+        if attempt == MAX_RETRIES {
+            break;
+        }
+        // Gitlab tells us how long to wait; if it doesn't, back off
+        // exponentially
+        let wait = resp
+            .headers()
+            .get(reqwest::header::RETRY_AFTER)
+            .and_then(|x| x.to_str().ok()?.parse::<u64>().ok())
+            .unwrap_or(1 << attempt);
+        warn!("Rate-limited by gitlab; retrying in {wait}s");
+        std::thread::sleep(std::time::Duration::from_secs(wait));
+    }
+    Err(anyhow!(
+        "Still rate-limited by gitlab after {MAX_RETRIES} retries"
+    ))
+}
+
+/// Get the MR's approval state: whether it's approved, and by whom
+pub fn query_approvals(
+    config: &GitlabConfig,
+    mr_iid: MergeRequestInternalId,
+) -> anyhow::Result<Approvals> {
+    info!("Querying for approvals");
+    let client = config.http_client()?;
+    get_json(
+        &client,
+        config,
+        &format!("merge_requests/{}/approvals", mr_iid.0),
+    )
+}
+
+/// Get the most recent comments on the MR, newest first
+pub fn query_comments(
+    config: &GitlabConfig,
+    mr_iid: MergeRequestInternalId,
+) -> anyhow::Result<Vec<Comment>> {
+    info!("Querying for comments");
+    let client = config.http_client()?;
+    get_json(
+        &client,
+        config,
+        &format!(
+            "merge_requests/{}/notes?sort=desc&order_by=created_at&per_page=100",
+            mr_iid.0
+        ),
+    )
+}
+
+/// Get all the MR's discussion threads, oldest first
+pub fn query_discussions(
+    config: &GitlabConfig,
+    mr_iid: MergeRequestInternalId,
+) -> anyhow::Result<Vec<Discussion>> {
+    info!("Querying for discussions");
+    let client = config.http_client()?;
+    get_all_pages(
+        &client,
+        config,
+        &format!("merge_requests/{}/discussions?per_page=100", mr_iid.0),
+    )
+}
+
+/// Get all the notes on the MR (comments and system notes), oldest first.
+/// Busy MRs have more than one page of these.
+pub fn query_notes(
+    config: &GitlabConfig,
+    mr_iid: MergeRequestInternalId,
+) -> anyhow::Result<Vec<Note>> {
+    info!("Querying for notes");
+    let client = config.http_client()?;
+    get_all_pages(
+        &client,
+        config,
+        &format!(
+            "merge_requests/{}/notes?sort=asc&order_by=created_at&per_page=100",
+            mr_iid.0
+        ),
+    )
+}
+
+/// Get the history of the MR's state, oldest first
+pub fn query_state_events(
+    config: &GitlabConfig,
+    mr_iid: MergeRequestInternalId,
+) -> anyhow::Result<Vec<StateEvent>> {
+    info!("Querying for state events");
+    let client = config.http_client()?;
+    get_all_pages(
+        &client,
+        config,
+        &format!(
+            "merge_requests/{}/resource_state_events?per_page=100",
+            mr_iid.0
+        ),
+    )
+}
+
+/// The MR's pipeline, re-using the cached one if nothing could have changed.
+///
+/// We only ask gitlab if the MR has been pushed to or updated since we last
+/// looked, or if the cached pipeline hadn't finished yet.
+fn refresh_pipeline(
+    client: &reqwest::blocking::Client,
+    config: &GitlabConfig,
+    mr: &MergeRequest,
+    cached: Option<&MergeRequest>,
+) -> Option<Pipeline> {
+    if let Some(cached) = cached {
+        let unchanged = cached.sha == mr.sha && cached.updated_at == mr.updated_at;
+        let finished = !cached
+            .pipeline
+            .as_ref()
+            .is_some_and(|x| pipeline_in_progress(&x.status));
+        if unchanged && finished {
+            debug!("MR hasn't changed; re-using the cached pipeline");
+            return cached.pipeline.clone();
+        }
+    }
+    match query_pipeline(client, config, mr.iid) {
+        Ok(x) => x,
+        Err(e) => {
+            warn!("Couldn't query the pipeline: {e}");
+            cached.and_then(|x| x.pipeline.clone())
+        }
+    }
+}
+
+/// Whether a pipeline with this status might still change
+fn pipeline_in_progress(status: &str) -> bool {
+    matches!(
+        status,
+        "created" | "waiting_for_resource" | "preparing" | "pending" | "running" | "scheduled"
+    )
+}
+
+/// Get the most recent pipeline for the MR, if it has one
+fn query_pipeline(
+    client: &reqwest::blocking::Client,
+    config: &GitlabConfig,
+    mr_iid: MergeRequestInternalId,
+) -> anyhow::Result<Option<Pipeline>> {
+    info!("Querying for pipelines");
+    let resp: Vec<Pipeline> = get_json(
+        client,
+        config,
+        &format!("merge_requests/{}/pipelines", mr_iid.0),
+    )?;
+    // They come most recent first
+    Ok(resp.into_iter().next())
+}
+
 /// Get the version history from gitlab.  If this endpoint is available,
 /// it's the best thing to use.
 ///
@@ -295,14 +1061,11 @@ fn query_versions(
     versions: &BTreeMap<Version, VersionInfo>,
 ) -> anyhow::Result<Vec<(Version, VersionInfo)>> {
     info!("Querying for versions");
-    let resp: Vec<serde_json::Value> = client
-        .get(format!(
-            "https://{}/api/v4/projects/{}/merge_requests/{}/versions",
-            config.host, config.project_id.0, mr_iid.0,
-        ))
-        .header("PRIVATE-TOKEN", &config.token)
-        .send()?
-        .json()?;
+    let resp: Vec<serde_json::Value> = get_json(
+        client,
+        config,
+        &format!("merge_requests/{}/versions", mr_iid.0),
+    )?;
 
     fn json_to_base(x: &serde_json::Value) -> anyhow::Result<ObjectId> {
         x["base_commit_sha"]
@@ -321,16 +1084,16 @@ fn query_versions(
         Some(first) => {
             let base = json_to_base(first)?;
             let head = json_to_head(first)?;
-            versions
+            let existing = versions
                 .iter()
                 .rev()
                 .find(|(_, x)| x.head == head && x.base == base)
-                .map(|(x, _)| *x)
-                .or_else(|| {
-                    let (latest, _) = versions.last_key_value()?;
-                    Some(Version(latest.0 + 1))
-                })
-                .unwrap_or(Version(0))
+                .map(|(x, _)| *x);
+            match (existing, versions.last_key_value()) {
+                (Some(x), _) => x,
+                (None, Some((latest, _))) => latest.plus(1)?,
+                (None, None) => Version(0),
+            }
         }
         None => return Ok(vec![]),
     };
@@ -338,10 +1101,11 @@ fn query_versions(
         .rev()
         .enumerate()
         .map(|(i, x)| {
-            let version = Version(start_at.0 + i as u8);
+            let version = start_at.plus(i)?;
             let info = VersionInfo {
                 base: json_to_base(&x)?,
                 head: json_to_head(&x)?,
+                created_at: None,
             };
             Ok((version, info))
         })
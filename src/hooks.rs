@@ -0,0 +1,113 @@
+use git2::Repository;
+use std::path::PathBuf;
+
+const BEGIN: &str = "# orpa-begin";
+const END: &str = "# orpa-end";
+
+/// The part of the post-commit hook which belongs to orpa
+fn section() -> String {
+    format!(
+        "{}\norpa mark HEAD \"$(git config orpa.autoMarkVerb || echo Authored)\"\n{}\n",
+        BEGIN, END
+    )
+}
+
+fn hook_path(repo: &Repository) -> anyhow::Result<PathBuf> {
+    let dir = match repo.config()?.get_path("core.hooksPath") {
+        Ok(x) => x,
+        Err(_) => repo.path().join("hooks"),
+    };
+    Ok(dir.join("post-commit"))
+}
+
+/// Remove orpa's section from the hook, if it has one
+fn strip_section(hook: &str) -> String {
+    let mut ret = String::new();
+    let mut inside = false;
+    for line in hook.lines() {
+        match line.trim() {
+            BEGIN => inside = true,
+            END => inside = false,
+            _ if !inside => {
+                ret.push_str(line);
+                ret.push('\n');
+            }
+            _ => (),
+        }
+    }
+    ret
+}
+
+/// Add a post-commit hook which marks each new commit.  If there's already a
+/// post-commit hook, orpa's section is appended to it (or replaced, if it's
+/// already there).
+pub fn install(repo: &Repository, dry_run: bool) -> anyhow::Result<()> {
+    let path = hook_path(repo)?;
+    let new_hook = match std::fs::read_to_string(&path) {
+        Ok(existing) => {
+            let mut hook = strip_section(&existing);
+            if !hook.is_empty() && !hook.ends_with("\n\n") {
+                hook.push('\n');
+            }
+            hook.push_str(&section());
+            hook
+        }
+        Err(_) => format!("#!/bin/sh\n\n{}", section()),
+    };
+    if dry_run {
+        println!("Would write {}:", path.display());
+        println!();
+        print!("{}", new_hook);
+        return Ok(());
+    }
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    std::fs::write(&path, new_hook)?;
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755))?;
+    }
+    println!("Installed {}", path.display());
+    Ok(())
+}
+
+/// Remove orpa's section from the post-commit hook.  If that leaves nothing
+/// but the shebang, the hook is deleted.
+pub fn uninstall(repo: &Repository, dry_run: bool) -> anyhow::Result<()> {
+    let path = hook_path(repo)?;
+    let existing = match std::fs::read_to_string(&path) {
+        Ok(x) => x,
+        Err(_) => {
+            println!("{} doesn't exist; nothing to do", path.display());
+            return Ok(());
+        }
+    };
+    let new_hook = strip_section(&existing);
+    if new_hook == existing {
+        println!("{} has no orpa section; nothing to do", path.display());
+        return Ok(());
+    }
+    let is_empty = new_hook
+        .lines()
+        .all(|x| x.trim().is_empty() || x.starts_with("#!"));
+    if dry_run {
+        if is_empty {
+            println!("Would delete {}", path.display());
+        } else {
+            println!("Would write {}:", path.display());
+            println!();
+            print!("{}", new_hook);
+        }
+        return Ok(());
+    }
+    if is_empty {
+        std::fs::remove_file(&path)?;
+        println!("Deleted {}", path.display());
+    } else {
+        std::fs::write(&path, new_hook.trim_end().to_owned() + "\n")?;
+        println!("Removed orpa from {}", path.display());
+    }
+    Ok(())
+}
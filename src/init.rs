@@ -0,0 +1,136 @@
+use crate::doctor::current_user;
+use crate::{config, db_path, http_proxy, OPTS};
+use anyhow::anyhow;
+use dialoguer::{Input, Password};
+use git2::Repository;
+
+/// Whatever was given on the command line.  Anything missing is asked for.
+pub struct Settings {
+    pub url: Option<String>,
+    pub project_id: Option<u64>,
+    pub token: Option<String>,
+    pub username: Option<String>,
+}
+
+pub fn init(
+    repo: &Repository,
+    force: bool,
+    no_prompt: bool,
+    given: Settings,
+) -> anyhow::Result<()> {
+    let config = repo.config()?.snapshot()?;
+    let existing = |key: &str| config::get_string(&config, key).ok();
+    let missing = |flag: &str| anyhow!("--no-prompt needs --{}", flag);
+
+    let url = match given.url {
+        Some(x) => x,
+        None if no_prompt => return Err(missing("url")),
+        None => Input::<String>::new()
+            .with_prompt("Gitlab host")
+            .default(existing("gitlab.url").unwrap_or_else(|| "gitlab.com".into()))
+            .interact_text()?,
+    };
+    // We add the scheme ourselves
+    let url = url
+        .trim_start_matches("https://")
+        .trim_start_matches("http://")
+        .trim_end_matches('/')
+        .to_owned();
+    if url.is_empty() {
+        return Err(anyhow!("The gitlab host can't be empty"));
+    }
+
+    let project_id = match given.project_id {
+        Some(x) => x,
+        None if no_prompt => return Err(missing("project-id")),
+        None => {
+            let mut input = Input::<u64>::new().with_prompt("Project ID");
+            if let Some(x) = existing("gitlab.projectId").and_then(|x| x.parse().ok()) {
+                input = input.default(x);
+            }
+            input.interact_text()?
+        }
+    };
+
+    let proxy = http_proxy(&config);
+    let mut token = given.token;
+    let user = loop {
+        let tok = match &token {
+            Some(x) => x.clone(),
+            None if no_prompt => return Err(missing("token")),
+            None => {
+                let old = existing("gitlab.privateToken");
+                let prompt = if old.is_some() {
+                    "API token (leave empty to keep the current one)"
+                } else {
+                    "API token"
+                };
+                let x = Password::new()
+                    .with_prompt(prompt)
+                    .allow_empty_password(old.is_some())
+                    .interact()?;
+                if x.is_empty() {
+                    old.unwrap_or_default()
+                } else {
+                    x
+                }
+            }
+        };
+        if OPTS.offline {
+            println!("Offline mode: not checking the token");
+            token = Some(tok);
+            break None;
+        }
+        match current_user(&url, &tok, proxy.as_deref()) {
+            Ok(user) => {
+                println!("The token belongs to @{}", user);
+                token = Some(tok);
+                break Some(user);
+            }
+            Err(e) if no_prompt || token.is_some() => {
+                return Err(anyhow!("Couldn't check the token with {}: {}", url, e));
+            }
+            Err(e) => println!("Couldn't check the token with {}: {}", url, e),
+        }
+    };
+    let token = token.unwrap_or_default();
+
+    let username = match given.username {
+        Some(x) => x,
+        None => match user.or_else(|| existing("gitlab.username")) {
+            Some(x) if no_prompt => x,
+            None if no_prompt => return Err(missing("username")),
+            default => {
+                let mut input = Input::<String>::new().with_prompt("Gitlab username");
+                if let Some(x) = default {
+                    input = input.default(x);
+                }
+                input.interact_text()?
+            }
+        },
+    };
+
+    println!();
+    config::set(repo, "gitlab.url", &url)?;
+    config::set(repo, "gitlab.projectId", &project_id.to_string())?;
+    config::set(repo, "gitlab.privateToken", &token)?;
+    config::set(repo, "gitlab.username", &username)?;
+
+    let db_path = db_path(repo);
+    if db_path.exists() {
+        if force {
+            std::fs::remove_dir_all(&db_path)?;
+            println!("Deleted {}", db_path.display());
+        } else {
+            println!("Keeping the existing database at {}", db_path.display());
+        }
+    }
+    std::fs::create_dir_all(db_path.join("merge_requests"))?;
+
+    println!();
+    println!("All set!  Next, run:");
+    println!();
+    println!("    orpa fetch    # to download the open merge requests");
+    println!("    orpa          # to see what's waiting for review");
+    Ok(())
+}
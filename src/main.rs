@@ -1,6 +1,14 @@
+mod config;
+mod doctor;
+mod export;
 mod fetch;
+mod hooks;
+mod init;
 mod mr_db;
 mod review_db;
+mod stats;
+mod triage;
+mod tui;
 
 use crate::fetch::{fetch, MergeRequest, MergeRequestState, ProjectId};
 use crate::mr_db::{Version, VersionInfo};
@@ -9,8 +17,9 @@ use anyhow::anyhow;
 use bpaf::{Bpaf, Parser};
 use git2::{Commit, Oid, Repository};
 use globset::GlobSet;
+use itertools::Itertools;
 use mr_db::MRWithVersions;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::io::Write;
 use std::path::Path;
 use std::sync::{LazyLock, OnceLock};
@@ -28,8 +37,23 @@ pub struct Opts {
     pub db: Option<std::path::PathBuf>,
     #[bpaf(long)]
     pub dedup: bool,
+    /// How similar a commit must be to a reviewed one for --dedup to
+    /// consider it reviewed, between 0 (exclusive) and 1.  Defaults to 1,
+    /// meaning an exact match.
+    #[bpaf(
+        long,
+        argument("SCORE"),
+        guard(valid_threshold, "the dedup threshold must be in the range (0, 1]")
+    )]
+    pub dedup_threshold: Option<f64>,
     #[bpaf(long)]
     pub notes_ref: Option<String>,
+    /// Print machine-readable output, where supported
+    #[bpaf(long)]
+    pub json: bool,
+    /// Don't talk to gitlab; use the cached MRs only
+    #[bpaf(long)]
+    pub offline: bool,
     #[bpaf(external, fallback(Cmd::default()))]
     pub cmd: Cmd,
 }
@@ -37,30 +61,130 @@ pub struct Opts {
 pub enum Cmd {
     #[default]
     Summary,
+    /// Show the unreviewed merge requests and commits which most need
+    /// attention
+    #[bpaf(command)]
+    Triage {
+        /// How many items to show.  Defaults to 5.
+        #[bpaf(long, argument("N"))]
+        count: Option<usize>,
+    },
+    /// Show how much reviewing someone has waiting for them
+    #[bpaf(command)]
+    ReviewLoad {
+        /// Their gitlab username.  Defaults to gitlab.username.
+        #[bpaf(positional("USERNAME"))]
+        reviewer: Option<String>,
+    },
     /// Summarize the review status of a branch
     #[bpaf(command)]
     Branch {
+        /// Keep running, and update the summary when new commits arrive.
+        #[bpaf(long)]
+        watch: bool,
+        /// How often to poll for changes when watching, in seconds.
+        /// Defaults to the value of orpa.watchInterval, or 30.
+        #[bpaf(long, argument("SECS"))]
+        interval: Option<u64>,
         #[bpaf(positional)]
         range: Option<String>,
     },
     /// Inspect the oldest unreviewed commit
     #[bpaf(command)]
     Next {
+        /// Consider merge commits too
+        #[bpaf(long("include-merge-commits"))]
+        include_merges: bool,
         #[bpaf(positional)]
         range: Option<String>,
     },
     /// List all unreviewed commits
     #[bpaf(command)]
     List {
+        /// Group the commits by author, with the biggest backlog first
+        #[bpaf(long)]
+        group_by_author: bool,
+        /// Group the commits by the merge request they belong to
+        #[bpaf(long)]
+        by_mr: bool,
+        /// Don't look the commits up in the cached merge requests, even if
+        /// --by-mr is given.  This is faster when there are lots of them.
+        #[bpaf(long)]
+        no_mr_lookup: bool,
+        /// List merge commits too, marked with "[merge]"
+        #[bpaf(long("include-merge-commits"))]
+        include_merges: bool,
+        /// Only list commits which touch files matching this glob
+        #[bpaf(long, argument("GLOB"))]
+        path: Option<String>,
+        /// Ignore --path.  Checking the diff of every commit can be slow for
+        /// big ranges.
+        #[bpaf(long)]
+        no_diff_filter: bool,
+        /// How to print each commit.  The placeholders are %H (hash), %h
+        /// (short hash), %s (summary), %an (author name), %ae (author email),
+        /// %ai (author date), and %N (note).  Defaults to "%H".
+        #[bpaf(long, argument("TEMPLATE"))]
+        format: Option<String>,
+        /// List the bookmarked commits instead, with their notes
+        #[bpaf(long)]
+        bookmarks: bool,
+        #[bpaf(positional)]
+        range: Option<String>,
+    },
+    /// Print the number of unreviewed commits
+    #[bpaf(command)]
+    Count {
+        /// Exit with status 1 if there are any unreviewed commits
+        #[bpaf(long)]
+        nonzero_exit: bool,
+        #[bpaf(positional)]
+        range: Option<String>,
+    },
+    /// Browse and mark unreviewed commits in an interactive UI
+    #[bpaf(command)]
+    Tui {
+        #[bpaf(positional)]
+        range: Option<String>,
+    },
+    /// Show how much code is waiting to be reviewed
+    ///
+    /// Adds up the diffs of all unreviewed commits, and shows the files with
+    /// the most changes.
+    #[bpaf(command)]
+    DiffStat {
         #[bpaf(positional)]
         range: Option<String>,
     },
+    /// List the files touched by a commit
+    #[bpaf(command)]
+    FilesChanged {
+        /// Only print the number of files
+        #[bpaf(long)]
+        count: bool,
+        #[bpaf(positional, complete(complete_revspec))]
+        revspec: String,
+    },
+    /// Show exactly what's stored in a commit's note
+    #[bpaf(command)]
+    NoteView {
+        #[bpaf(positional, complete(complete_revspec))]
+        revspec: String,
+    },
     /// Show the status of a commit
     #[bpaf(command)]
     Show {
         /// The commit to show the status of.  It can be a revision such as
         /// "c13f2b6", or a ref such as "origin/master" or "HEAD".
-        #[bpaf(positional)]
+        #[bpaf(positional, complete(complete_revspec))]
+        revspec: String,
+    },
+    /// Explain why a commit has the status it does
+    #[bpaf(command)]
+    Explain {
+        /// The commit to explain.  It can be a revision such as "c13f2b6",
+        /// or a ref such as "origin/master" or "HEAD".
+        #[bpaf(positional, complete(complete_revspec))]
         revspec: String,
     },
     /// Attach a note to a commit
@@ -70,36 +194,448 @@ pub enum Cmd {
     /// `orpa mark HEAD Tested` will attach the following note to HEAD:
     /// "Tested-by: Joe Smith <joe@smith.net>".  If no note is provided,
     /// the verb "Reviewed" is used.
+    ///
+    /// If no commit is given, you'll be asked to pick from the unreviewed
+    /// commits on the current branch.
     #[bpaf(command)]
     Mark {
         /// The commit to attach a note to.  It can be a revision such as
         /// "c13f2b6", or a ref such as "origin/master" or "HEAD".
-        #[bpaf(positional)]
-        revspec: String,
+        #[bpaf(positional, complete(complete_opt_revspec))]
+        revspec: Option<String>,
         /// The note to attach.
         #[bpaf(positional)]
         note: Option<String>,
     },
+    /// Attach a note to every commit in FROM..TO
+    ///
+    /// Commits which already have a note are left alone.  FROM must be an
+    /// ancestor of TO.
+    #[bpaf(command)]
+    MarkRange {
+        /// Print the commits which would be marked, without marking them
+        #[bpaf(long)]
+        dry_run: bool,
+        /// The last commit before the range (not marked itself)
+        #[bpaf(positional("FROM"), complete(complete_revspec))]
+        from: String,
+        /// The last commit in the range
+        #[bpaf(positional("TO"), complete(complete_revspec))]
+        to: String,
+        /// The note to attach.  Defaults to "Reviewed".
+        #[bpaf(positional)]
+        note: Option<String>,
+    },
+    /// Record that you've decided not to review a commit
+    ///
+    /// Skipped commits are no longer listed as awaiting review, but they're
+    /// not counted as reviewed either.
+    #[bpaf(command)]
+    Skip {
+        /// The commit to skip.  It can be a revision such as "c13f2b6", or
+        /// a ref such as "origin/master" or "HEAD".
+        #[bpaf(positional, complete(complete_revspec))]
+        revspec: String,
+    },
+    /// Save a commit to review later
+    ///
+    /// Bookmarked commits aren't listed as awaiting review; use
+    /// `orpa list --bookmarks` to find them again.
+    #[bpaf(command)]
+    Bookmark {
+        /// A reminder of why you bookmarked it
+        #[bpaf(long, argument("NOTE"))]
+        note: Option<String>,
+        /// The commit to bookmark
+        #[bpaf(positional, complete(complete_revspec))]
+        revspec: String,
+    },
+    /// Remove a commit's bookmark, so that it's listed as unreviewed again
+    #[bpaf(command)]
+    Unbookmark {
+        #[bpaf(positional, complete(complete_revspec))]
+        revspec: String,
+    },
+    /// Copy a note from one commit to another
+    ///
+    /// This is useful when a reviewed commit has been cherry-picked.  The
+    /// two commits must have identical diffs, unless --force is given.
+    #[bpaf(command)]
+    TransferNote {
+        /// Copy the note even if the commits' diffs differ
+        #[bpaf(long)]
+        force: bool,
+        /// The commit to copy the note from
+        #[bpaf(positional("FROM"))]
+        from: String,
+        /// The commit to copy the note to
+        #[bpaf(positional("TO"))]
+        to: String,
+    },
+    /// Copy a commit's note to another commit verbatim, eg. after amending it
+    #[bpaf(command)]
+    CopyNote {
+        /// Copy the note even if the destination already has one
+        #[bpaf(long)]
+        force: bool,
+        /// The commit to copy the note from
+        #[bpaf(positional("FROM"), complete(complete_revspec))]
+        from: String,
+        /// The commit to copy the note to
+        #[bpaf(positional("TO"), complete(complete_revspec))]
+        to: String,
+    },
+    /// Move notes from rewritten commits to their replacements, eg. after
+    /// `git rebase -i`
+    #[bpaf(command)]
+    RewriteNotes {
+        /// A file of "<old> <new>" lines, as given to the post-rewrite hook.
+        /// Defaults to the list left by an in-progress rebase.
+        #[bpaf(long, argument("PATH"))]
+        map_file: Option<PathBuf>,
+    },
     /// Approve a commit and all its ancestors
     #[bpaf(command)]
     Checkpoint {
+        /// Only apply the checkpoint when reviewing this branch
+        #[bpaf(long, argument("BRANCH"))]
+        branch: Option<String>,
         /// The commit to mark as a checkpoint.  It can be a revision such as
         /// "c13f2b6", or a ref such as "origin/master" or "HEAD".
-        #[bpaf(positional)]
+        #[bpaf(positional, complete(complete_revspec))]
         revspec: String,
     },
     /// Speed up future operations
     #[bpaf(command)]
     Gc,
+    /// Delete the refs under refs/orpa which belong to closed or merged MRs
+    #[bpaf(command)]
+    Prune {
+        /// Only list the refs which would be deleted
+        #[bpaf(long)]
+        dry_run: bool,
+    },
+    /// Shrink the on-disk database by rewriting it
+    #[bpaf(command)]
+    CompactDb {
+        /// Only print the current size of the database
+        #[bpaf(long)]
+        dry_run: bool,
+        /// Also drop popular lines from the index, as `orpa gc` does
+        #[bpaf(long)]
+        compact_index: bool,
+    },
+    /// Throw away the line index and build it again from your notes
+    #[bpaf(command)]
+    RebuildIndex {
+        /// Report progress as the commits are indexed
+        #[bpaf(long)]
+        verbose: bool,
+    },
+    /// Check that the line index is consistent
+    ///
+    /// Exits with status 1 if any problems are left.
+    #[bpaf(command)]
+    VerifyDb {
+        /// Remove the bad entries.  They'll be rebuilt as needed.
+        #[bpaf(long)]
+        fix: bool,
+    },
+    /// Install a post-commit hook which marks your new commits
+    ///
+    /// The hook runs `orpa mark HEAD` with the verb from orpa.autoMarkVerb
+    /// (or "Authored").  An existing post-commit hook is kept, and orpa's
+    /// part is added to the end.
+    #[bpaf(command)]
+    HooksInstall {
+        /// Print the new hook instead of writing it
+        #[bpaf(long)]
+        dry_run: bool,
+    },
+    /// Remove orpa's part of the post-commit hook
+    #[bpaf(command)]
+    HooksUninstall {
+        /// Print the new hook instead of writing it
+        #[bpaf(long)]
+        dry_run: bool,
+    },
+    /// Show or change orpa's settings in git-config
+    ///
+    /// With no arguments, prints all the settings orpa knows about.
+    #[bpaf(command, footer(config::keys_help()))]
+    Config {
+        /// The key to show, set, or unset, eg. "gitlab.url"
+        #[bpaf(long, argument("KEY"))]
+        key: Option<String>,
+        /// Set the key to this value in the repo's .git/config
+        #[bpaf(long, argument("VALUE"))]
+        value: Option<String>,
+        /// Remove the key from the repo's .git/config
+        #[bpaf(long)]
+        unset: bool,
+    },
+    /// Set orpa up for this repository
+    ///
+    /// Asks for the gitlab settings (unless they're given as flags), checks
+    /// them, and saves them in the repo's .git/config.
+    #[bpaf(command)]
+    Init {
+        /// Delete orpa's existing database (but not your notes) and start
+        /// again
+        #[bpaf(long)]
+        force: bool,
+        /// Don't ask any questions; all the settings must be given as flags
+        #[bpaf(long)]
+        no_prompt: bool,
+        /// The gitlab host, eg. gitlab.example.com
+        #[bpaf(long, argument("HOST"))]
+        url: Option<String>,
+        /// The numeric ID of the gitlab project
+        #[bpaf(long, argument("ID"))]
+        project_id: Option<u64>,
+        /// An API token for gitlab
+        #[bpaf(long, argument("TOKEN"))]
+        token: Option<String>,
+        /// Your gitlab username
+        #[bpaf(long, argument("NAME"))]
+        username: Option<String>,
+    },
+    /// Check that orpa is set up correctly
+    ///
+    /// The exit status is the number of checks which failed.
+    #[bpaf(command)]
+    Doctor,
+    /// Save your notes and cached MRs to a file
+    ///
+    /// The file can be loaded into another clone with `orpa import`.
+    #[bpaf(command)]
+    Export {
+        #[bpaf(positional("FILE"))]
+        output: PathBuf,
+    },
+    /// Load notes and MRs saved by `orpa export`
+    ///
+    /// Notes are merged with the ones you already have.
+    #[bpaf(command)]
+    Import {
+        #[bpaf(positional("FILE"))]
+        input: PathBuf,
+    },
     /// Sync MRs from gitlab
     #[bpaf(command)]
-    Fetch,
+    Fetch {
+        /// Query gitlab for one MR at a time
+        #[bpaf(long)]
+        no_parallel: bool,
+        #[bpaf(external(merge_notes))]
+        merge_notes: bool,
+        /// Only sync this project (when several are configured)
+        #[bpaf(long, argument("PROJECT_ID"))]
+        project: Option<u64>,
+        /// Also fetch MRs which were merged or closed recently (within
+        /// orpa.closedMrWindow days; default: 90)
+        #[bpaf(long)]
+        closed: bool,
+    },
+    /// Sync one MR from gitlab
+    ///
+    /// This is quicker than `orpa fetch` when you know which MR has changed.
+    /// The MR must already be in the cache.
+    #[bpaf(command)]
+    FetchSingle {
+        /// The merge request to update.  Must be an integer.  It can
+        /// optionally be prefixed with a '!'.
+        #[bpaf(positional, complete(complete_mr_id))]
+        id: String,
+    },
     /// Show a specific merge request
     #[bpaf(command)]
     Mr {
         /// The merge request to show.  Must be an integer.  It can optionally
         /// be prefixed with a '!'.
-        #[bpaf(positional)]
+        #[bpaf(positional, complete(complete_mr_id))]
+        id: String,
+    },
+    /// Show the CI status of a merge request
+    #[bpaf(command)]
+    Pipeline {
+        /// The merge request to show.  Must be an integer.  It can optionally
+        /// be prefixed with a '!'.
+        #[bpaf(positional, complete(complete_mr_id))]
+        id: String,
+    },
+    /// Check whether the target branches of open merge requests have moved,
+    /// and update the base of their latest version if so
+    #[bpaf(command)]
+    MrBaseChange {
+        /// Only check this merge request
+        #[bpaf(long, argument("ID"), complete(complete_opt_mr_id))]
+        id: Option<String>,
+    },
+    /// Record gitlab approvals as review notes on the approved commits
+    #[bpaf(command)]
+    SyncApprovals {
+        /// Only sync this merge request.  By default, all open merge
+        /// requests are synced.
+        #[bpaf(long, argument("ID"), complete(complete_opt_mr_id))]
+        id: Option<String>,
+        /// Show the notes which would be added, without adding them
+        #[bpaf(long)]
+        dry_run: bool,
+    },
+    /// Show who has approved a merge request on gitlab, and who has only
+    /// reviewed it locally
+    #[bpaf(command)]
+    MrApprovals {
+        /// The merge request to show.  Must be an integer.  It can optionally
+        /// be prefixed with a '!'.
+        #[bpaf(positional, complete(complete_mr_id))]
+        id: String,
+    },
+    /// Show what changed in a merge request between two versions
+    ///
+    /// By default, the last two versions are compared.
+    #[bpaf(command)]
+    DiffVersions {
+        /// The older version (eg. 1 for v1).  Defaults to the version
+        /// before --to.
+        #[bpaf(long, argument("VERSION"))]
+        from: Option<u16>,
+        /// The newer version.  Defaults to the latest version.
+        #[bpaf(long, argument("VERSION"))]
+        to: Option<u16>,
+        /// The merge request to show.  Must be an integer.  It can optionally
+        /// be prefixed with a '!'.
+        #[bpaf(positional, complete(complete_mr_id))]
+        id: String,
+    },
+    /// Check out the head of a merge request (as a detached HEAD)
+    #[bpaf(command)]
+    MrCheckout {
+        /// The version to check out (eg. 1 for v1).  Defaults to the latest.
+        #[bpaf(long, argument("VERSION"))]
+        version: Option<u16>,
+        /// The merge request to check out.  Must be an integer.  It can
+        /// optionally be prefixed with a '!'.
+        #[bpaf(positional, complete(complete_mr_id))]
+        id: String,
+    },
+    /// Show the full patch of every commit in a merge request
+    #[bpaf(command)]
+    MrDiff {
+        /// The version to show (eg. 1 for v1).  Defaults to the latest.
+        #[bpaf(long, argument("VERSION"))]
+        version: Option<u16>,
+        /// The merge request to show.  Must be an integer.  It can optionally
+        /// be prefixed with a '!'.
+        #[bpaf(positional, complete(complete_mr_id))]
+        id: String,
+    },
+    /// Write the commits of a merge request's latest version out as patch
+    /// files, like `git format-patch`
+    ///
+    /// Merge commits are left out.
+    #[bpaf(command)]
+    MrPatch {
+        /// Where to put the patches.  Defaults to the current directory.
+        #[bpaf(short('o'), long, argument("DIR"), fallback(PathBuf::from(".")))]
+        output_dir: PathBuf,
+        /// The merge request to export.  Must be an integer.  It can
+        /// optionally be prefixed with a '!'.
+        #[bpaf(positional, complete(complete_mr_id))]
+        id: String,
+    },
+    /// Show everything that's happened to a merge request, in order
+    ///
+    /// This combines gitlab's history of the MR with the versions orpa has
+    /// seen and the notes you've left on its commits.
+    #[bpaf(command)]
+    MrTimeline {
+        /// The merge request to show.  Must be an integer.  It can optionally
+        /// be prefixed with a '!'.
+        #[bpaf(positional, complete(complete_mr_id))]
+        id: String,
+    },
+    /// Estimate how big a merge request is, and how long it'll take to
+    /// review
+    #[bpaf(command)]
+    MrSize {
+        /// The merge request to measure.  Must be an integer.  It can
+        /// optionally be prefixed with a '!'.
+        #[bpaf(positional, complete(complete_mr_id))]
+        id: String,
+    },
+    /// Show the net change of a merge request's latest version, as a
+    /// squash-merge would produce it
+    #[bpaf(command)]
+    MrSquashPreview {
+        /// Show the full patch, not just the diffstat
+        #[bpaf(long)]
+        diff: bool,
+        /// The merge request to show.  Must be an integer.  It can optionally
+        /// be prefixed with a '!'.
+        #[bpaf(positional, complete(complete_mr_id))]
+        id: String,
+    },
+    /// List the files which conflict when merging a merge request into
+    /// its target branch
+    ///
+    /// The merge is done in memory, against your local copy of the target
+    /// branch.
+    #[bpaf(command)]
+    MrConflicts {
+        /// The merge request to check.  Must be an integer.  It can
+        /// optionally be prefixed with a '!'.
+        #[bpaf(positional, complete(complete_mr_id))]
+        id: String,
+    },
+    /// Show the discussion threads on a merge request
+    #[bpaf(command)]
+    MrComments {
+        /// Hide threads which have been resolved
+        #[bpaf(long)]
+        unresolved_only: bool,
+        /// The merge request to show.  Must be an integer.  It can optionally
+        /// be prefixed with a '!'.
+        #[bpaf(positional, complete(complete_mr_id))]
+        id: String,
+    },
+    /// Record locally who's reviewing a merge request
+    ///
+    /// This doesn't touch gitlab.  With no flags, shows the current claim.
+    #[bpaf(command)]
+    MrTracker {
+        /// Claim the MR for yourself
+        #[bpaf(long)]
+        claim: bool,
+        /// Drop the claim on the MR
+        #[bpaf(long)]
+        release: bool,
+        /// The merge request.  Must be an integer.  It can optionally be
+        /// prefixed with a '!'.
+        #[bpaf(positional, complete(complete_mr_id))]
+        id: String,
+    },
+    /// Create a local branch pointing at the head of a merge request
+    #[bpaf(command)]
+    MrLink {
+        /// The name of the branch.  Defaults to "mr/<id>".
+        #[bpaf(long, argument("NAME"))]
+        branch_name: Option<String>,
+        /// Move the branch if it already exists
+        #[bpaf(long)]
+        force: bool,
+        /// The merge request to link.  Must be an integer.  It can optionally
+        /// be prefixed with a '!'.
+        #[bpaf(positional, complete(complete_mr_id))]
+        id: String,
+    },
+    /// Delete the branch created by `orpa mr-link`
+    #[bpaf(command)]
+    MrUnlink {
+        /// The merge request to unlink.  Must be an integer.  It can
+        /// optionally be prefixed with a '!'.
+        #[bpaf(positional, complete(complete_mr_id))]
         id: String,
     },
     /// Show merge requests
@@ -110,15 +646,334 @@ pub enum Cmd {
         /// Include hidden MRs.
         #[bpaf(long, short)]
         all: bool,
+        /// How to order the MRs.  One of "updated-at" (the default),
+        /// "created-at", "title", "author", or "unreviewed-count".
+        #[bpaf(long, argument("ORDER"))]
+        sort: Option<MrsSort>,
+        /// Only show MRs with this label (case-insensitive)
+        #[bpaf(long, argument("LABEL"))]
+        label: Option<String>,
+        /// Only show MRs by this author.  Matches the gitlab username, or
+        /// failing that, part of the author's name (case-insensitive).
+        #[bpaf(long, argument("USER"))]
+        author: Option<String>,
+        /// Only show MRs which target this branch (case-sensitive)
+        #[bpaf(long, argument("BRANCH"))]
+        target_branch: Option<String>,
+        /// Only show MRs updated after this date (YYYY-MM-DD, or an RFC 3339
+        /// timestamp)
+        #[bpaf(long, argument("DATE"))]
+        since: Option<String>,
+    },
+    /// Show MRs which have been waiting for review for a while
+    ///
+    /// Lists open MRs which have unreviewed commits, and which haven't been
+    /// updated recently.
+    #[bpaf(command)]
+    Remind {
+        /// Only show MRs which haven't been updated for this many days
+        /// (default: 7)
+        #[bpaf(long, argument("DAYS"))]
+        days: Option<u64>,
+        /// Post a comment on each MR to nudge its reviewers
+        #[bpaf(long)]
+        post_gitlab: bool,
+    },
+    /// List open MRs which nobody has touched for a while
+    ///
+    /// Unlike `orpa remind`, this includes MRs which have been fully
+    /// reviewed.
+    #[bpaf(command)]
+    Stale {
+        /// Only show MRs which haven't been updated for this many days
+        /// (default: 14)
+        #[bpaf(long, argument("DAYS"))]
+        days: Option<u64>,
+        /// Include your own MRs
+        #[bpaf(long)]
+        include_own: bool,
+    },
+    /// Post a reminder on MRs which have been waiting too long for review
+    ///
+    /// An MR is nagged if it has unreviewed commits, hasn't been updated for
+    /// a while, and hasn't been nagged already in that time.
+    #[bpaf(command)]
+    Nag {
+        /// Only consider this MR.  By default, all open MRs are checked.
+        #[bpaf(long, argument("ID"), complete(complete_opt_mr_id))]
+        id: Option<String>,
+        /// How many days an MR must be idle before it's nagged (default: 3)
+        #[bpaf(long, argument("DAYS"))]
+        days: Option<u64>,
+        /// Print the comments instead of posting them
+        #[bpaf(long)]
+        dry_run: bool,
+    },
+    /// Add someone to a merge request's reviewers or assignees on gitlab
+    #[bpaf(command)]
+    MrAssign {
+        /// "reviewer" (the default) or "assignee"
+        #[bpaf(long("as"), argument("ROLE"), fallback(AssignRole::Reviewer))]
+        role: AssignRole,
+        /// The merge request.  Must be an integer.  It can optionally be
+        /// prefixed with a '!'.
+        #[bpaf(positional("ID"), complete(complete_mr_id))]
+        id: String,
+        /// Their gitlab username
+        #[bpaf(positional("USERNAME"))]
+        username: String,
+    },
+    /// Print a shell completion script
+    ///
+    /// The shell can be "bash", "zsh", "fish", or "elvish".
+    #[bpaf(command)]
+    Completions {
+        #[bpaf(positional("SHELL"))]
+        shell: String,
     },
     /// Show recent reviews
     #[bpaf(command)]
-    Recent,
+    Recent {
+        /// Group the reviewed commits by who reviewed them
+        #[bpaf(long)]
+        by_reviewer: bool,
+    },
+    /// Find commits whose notes match a regex
+    ///
+    /// The pattern is matched against each line of the note separately.
     #[bpaf(command)]
-    Similar {
+    SearchNotes {
+        /// Ignore case when matching
+        #[bpaf(long, short('i'))]
+        case_insensitive: bool,
+        #[bpaf(positional("PATTERN"))]
+        pattern: String,
+    },
+    /// Show notes attached to commits which no longer exist
+    ///
+    /// This usually happens after a rebase.
+    #[bpaf(command)]
+    Orphans {
+        /// Delete the orphaned notes
+        #[bpaf(long)]
+        clean: bool,
+    },
+    /// Delete notes attached to commits which no longer exist
+    ///
+    /// Without --fix, this only counts the orphaned notes and shows a few
+    /// of them.
+    #[bpaf(command)]
+    GcNotes {
+        /// Delete the orphaned notes
+        #[bpaf(long)]
+        fix: bool,
+        /// Don't delete anything, even if --fix is given
+        #[bpaf(long)]
+        dry_run: bool,
+    },
+    /// Check the signatures of the commits in a range
+    ///
+    /// Signatures are checked with `git verify-commit`, so gpg needs to know
+    /// the signers' keys.
+    #[bpaf(command)]
+    VerifySignatures {
+        #[bpaf(positional)]
+        range: Option<String>,
+    },
+    /// Show who reviewed each commit in a range
+    #[bpaf(command)]
+    Blame {
+        #[bpaf(positional)]
+        range: Option<String>,
+    },
+    /// Show how much reviewing each person has done
+    #[bpaf(command)]
+    Audit {
+        /// Only show this reviewer (name or email)
+        #[bpaf(long, argument("REVIEWER"))]
+        reviewer: Option<String>,
+        /// Only count notes written on or after this date (YYYY-MM-DD, or an
+        /// RFC 3339 timestamp)
+        #[bpaf(long, argument("DATE"))]
+        since: Option<String>,
+    },
+    /// Show statistics about the review status of a branch
+    #[bpaf(command)]
+    Stats {
+        /// Only count commits by this author (name or email)
+        #[bpaf(long, argument("AUTHOR"))]
+        author: Option<String>,
         #[bpaf(positional)]
+        range: Option<String>,
+    },
+    #[bpaf(command)]
+    Similar {
+        #[bpaf(positional, complete(complete_revspec))]
         revspec: String,
     },
+    /// Compare the unreviewed commits on two branches
+    #[bpaf(command)]
+    Compare {
+        #[bpaf(positional("BRANCH1"), complete(complete_revspec))]
+        branch1: String,
+        #[bpaf(positional("BRANCH2"), complete(complete_revspec))]
+        branch2: String,
+    },
+}
+
+fn merge_notes() -> impl Parser<bool> {
+    let yes = bpaf::long("merge-notes")
+        .help("Also fetch other people's notes from all remotes, and merge them into ours")
+        .req_flag(true);
+    let no = bpaf::long("no-merge-notes")
+        .help("Don't fetch notes from the remotes (the default)")
+        .req_flag(false);
+    bpaf::construct!([yes, no]).fallback(false)
+}
+
+fn completions(shell: &str) -> anyhow::Result<()> {
+    let install = match shell {
+        "bash" => "orpa completions bash >> ~/.bash_completion",
+        "zsh" => "orpa completions zsh > ~/.zsh/_orpa  # somewhere in your $fpath",
+        "fish" => "orpa completions fish > ~/.config/fish/completions/orpa.fish",
+        "elvish" => "orpa completions elvish >> ~/.config/elvish/rc.elv",
+        _ => {
+            return Err(anyhow!(
+                "Unknown shell \"{}\" (expected bash, zsh, fish, or elvish)",
+                shell
+            ))
+        }
+    };
+    // bpaf generates the script when it sees this flag
+    let status = std::process::Command::new(std::env::current_exe()?)
+        .arg(format!("--bpaf-complete-style-{}", shell))
+        .status()?;
+    if !status.success() {
+        return Err(anyhow!("Couldn't generate the completion script"));
+    }
+    eprintln!("To install the completions, run:");
+    eprintln!();
+    eprintln!("    {}", install);
+    Ok(())
+}
+
+/// Suggest branches and recent commits
+#[allow(clippy::ptr_arg)] // bpaf wants this signature
+fn complete_revspec(input: &String) -> Vec<(String, Option<String>)> {
+    let Ok(repo) = Repository::open_from_env() else {
+        return vec![];
+    };
+    let mut ret = vec![];
+    if let Ok(branches) = repo.branches(None) {
+        for (branch, _) in branches.flatten() {
+            if let Ok(Some(name)) = branch.name() {
+                ret.push((name.to_owned(), None));
+            }
+        }
+    }
+    if let Ok(mut walk) = repo.revwalk() {
+        if walk.push_head().is_ok() {
+            for oid in walk.flatten().take(20) {
+                let Ok(c) = repo.find_commit(oid) else {
+                    continue;
+                };
+                let Ok(short_id) = c.as_object().short_id() else {
+                    continue;
+                };
+                let short_id = short_id.as_str().unwrap_or("").to_owned();
+                ret.push((short_id, c.summary().map(|x| x.to_owned())));
+            }
+        }
+    }
+    ret.retain(|(x, _)| x.starts_with(input.as_str()));
+    ret
+}
+
+fn complete_opt_revspec(input: &Option<String>) -> Vec<(String, Option<String>)> {
+    complete_revspec(input.as_ref().unwrap_or(&String::new()))
+}
+
+/// Suggest the IDs of cached MRs
+#[allow(clippy::ptr_arg)] // bpaf wants this signature
+fn complete_mr_id(input: &String) -> Vec<(String, Option<String>)> {
+    let Ok(repo) = Repository::open_from_env() else {
+        return vec![];
+    };
+    // We're still parsing the options, so we can't look at --db
+    let dir = repo.path().join("orpa").join("merge_requests");
+    let mut ret = vec![];
+    for path in mr_files_under(&dir).unwrap_or_default() {
+        let Ok(f) = File::open(path) else {
+            continue;
+        };
+        let Ok(mr) = serde_json::from_reader::<_, MRWithVersions>(f) else {
+            continue;
+        };
+        ret.push((mr.mr.iid.0.to_string(), Some(mr.mr.title)));
+    }
+    let input = input.trim_start_matches('!');
+    ret.retain(|(x, _)| x.starts_with(input));
+    ret.sort();
+    ret
+}
+
+fn complete_opt_mr_id(input: &Option<String>) -> Vec<(String, Option<String>)> {
+    complete_mr_id(input.as_ref().unwrap_or(&String::new()))
+}
+
+fn valid_threshold(x: &Option<f64>) -> bool {
+    x.is_none_or(|x| x > 0. && x <= 1.)
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MrsSort {
+    /// Most recently updated first
+    #[default]
+    UpdatedAt,
+    /// Most recently created first
+    CreatedAt,
+    Title,
+    Author,
+    /// Most unreviewed commits first
+    UnreviewedCount,
+}
+
+impl std::str::FromStr for MrsSort {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "updated-at" => Ok(MrsSort::UpdatedAt),
+            "created-at" => Ok(MrsSort::CreatedAt),
+            "title" => Ok(MrsSort::Title),
+            "author" => Ok(MrsSort::Author),
+            "unreviewed-count" => Ok(MrsSort::UnreviewedCount),
+            _ => Err(format!(
+                "Unknown sort order \"{}\" (expected one of updated-at, \
+                 created-at, title, author, unreviewed-count)",
+                s
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AssignRole {
+    Reviewer,
+    Assignee,
+}
+
+impl std::str::FromStr for AssignRole {
+    type Err = String;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "reviewer" => Ok(AssignRole::Reviewer),
+            "assignee" => Ok(AssignRole::Assignee),
+            _ => Err(format!(
+                "Unknown role \"{}\" (expected reviewer or assignee)",
+                s
+            )),
+        }
+    }
 }
 
 pub fn get_idx(repo: &Repository) -> anyhow::Result<&LineIdx> {
@@ -147,38 +1002,313 @@ fn main() -> anyhow::Result<()> {
     let repo = Repository::open_from_env()?;
     match OPTS.cmd.clone() {
         Cmd::Summary => summary(&repo),
-        Cmd::Branch { range } => branch(&repo, range),
-        Cmd::Next { range } => next(&repo, range),
-        Cmd::List { range } => list(&repo, range),
+        Cmd::Branch {
+            range,
+            watch: false,
+            ..
+        } => branch(&repo, range),
+        Cmd::Branch {
+            range,
+            watch: true,
+            interval,
+        } => watch_branch(&repo, range, interval),
+        Cmd::Next {
+            range,
+            include_merges,
+        } => next(&repo, range, include_merges),
+        Cmd::List {
+            range,
+            group_by_author,
+            by_mr,
+            no_mr_lookup,
+            include_merges,
+            path,
+            no_diff_filter,
+            format,
+            bookmarks,
+        } => {
+            let grouped = if bookmarks {
+                Some("--bookmarks")
+            } else if group_by_author {
+                Some("--group-by-author")
+            } else if by_mr && !no_mr_lookup {
+                Some("--by-mr")
+            } else {
+                None
+            };
+            if let Some(grouped) = grouped {
+                let flags = [
+                    (format.is_some(), "--format"),
+                    (path.is_some(), "--path"),
+                    (include_merges, "--include-merge-commits"),
+                ];
+                if let Some((_, flag)) = flags.iter().find(|x| x.0) {
+                    return Err(anyhow!("{} can't be used with {}", flag, grouped));
+                }
+            }
+            if bookmarks {
+                list_bookmarks(&repo, range)
+            } else if group_by_author {
+                list_by_author(&repo, range)
+            } else if by_mr && !no_mr_lookup {
+                list_by_mr(&repo, range)
+            } else {
+                let path = path.filter(|_| !no_diff_filter);
+                let format = format.as_deref().unwrap_or("%H");
+                list(&repo, range, include_merges, path, format)
+            }
+        }
+        Cmd::Count {
+            range,
+            nonzero_exit,
+        } => {
+            let mut n = 0;
+            walk_new(&repo, range.as_ref(), |_| n += 1)?;
+            println!("{}", n);
+            if nonzero_exit && n > 0 {
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Cmd::Tui { range } => tui::tui(&repo, range),
+        Cmd::DiffStat { range } => diff_stat(&repo, range),
+        Cmd::FilesChanged { revspec, count } => files_changed(&repo, &revspec, count),
+        Cmd::NoteView { revspec } => note_view(&repo, &revspec),
         Cmd::Show { revspec } => show(&repo, &revspec),
-        Cmd::Mark { revspec, note } => add_note(
+        Cmd::Explain { revspec } => explain(&repo, &revspec),
+        Cmd::Mark {
+            revspec: Some(revspec),
+            note,
+        } => add_note(
             &repo,
             repo.revparse_single(&revspec)?.peel_to_commit()?.id(),
             note.as_ref().map_or("Reviewed", |x| x.as_str()),
         ),
-        Cmd::Checkpoint { revspec } => append_note(
+        Cmd::Mark {
+            revspec: None,
+            note,
+        } => mark_interactive(&repo, note.as_ref().map_or("Reviewed", |x| x.as_str())),
+        Cmd::MarkRange {
+            from,
+            to,
+            note,
+            dry_run,
+        } => mark_range(
+            &repo,
+            &from,
+            &to,
+            note.as_ref().map_or("Reviewed", |x| x.as_str()),
+            dry_run,
+        ),
+        Cmd::Skip { revspec } => add_note(
             &repo,
             repo.revparse_single(&revspec)?.peel_to_commit()?.id(),
-            "checkpoint",
+            "Skipped",
         ),
-        Cmd::Gc => Err(anyhow!("Auto-checkpointing not implemented yet")),
-        Cmd::Fetch => fetch(&repo),
-        Cmd::Mr { id } => merge_request(&repo, id),
-        Cmd::Mrs { all } => merge_requests(&repo, all),
-        Cmd::Recent => {
-            for x in review_db::recent_notes(&repo)? {
-                println!("{}", x);
-            }
+        Cmd::Bookmark { revspec, note } => bookmark(
+            &repo,
+            repo.revparse_single(&revspec)?.peel_to_commit()?.id(),
+            note.as_deref(),
+        ),
+        Cmd::Unbookmark { revspec } => unbookmark(
+            &repo,
+            repo.revparse_single(&revspec)?.peel_to_commit()?.id(),
+        ),
+        Cmd::TransferNote { from, to, force } => transfer_note(&repo, &from, &to, force),
+        Cmd::CopyNote { from, to, force } => copy_note(&repo, &from, &to, force),
+        Cmd::RewriteNotes { map_file } => rewrite_notes(&repo, map_file),
+        Cmd::Checkpoint { revspec, branch } => append_note(
+            &repo,
+            repo.revparse_single(&revspec)?.peel_to_commit()?.id(),
+            &checkpoint_note(branch.as_deref()),
+        ),
+        Cmd::HooksInstall { dry_run } => hooks::install(&repo, dry_run),
+        Cmd::HooksUninstall { dry_run } => hooks::uninstall(&repo, dry_run),
+        Cmd::Config { key, value, unset } => match (key, value, unset) {
+            (None, None, false) => config::show_all(&repo),
+            (Some(key), None, false) => config::show(&repo, &key),
+            (Some(key), Some(value), false) => config::set(&repo, &key, &value),
+            (Some(key), None, true) => config::unset(&repo, &key),
+            (None, _, _) => Err(anyhow!("--value and --unset need a --key")),
+            (Some(_), Some(_), true) => Err(anyhow!("Use either --value or --unset, not both")),
+        },
+        Cmd::Init {
+            force,
+            no_prompt,
+            url,
+            project_id,
+            token,
+            username,
+        } => init::init(
+            &repo,
+            force,
+            no_prompt,
+            init::Settings {
+                url,
+                project_id,
+                token,
+                username,
+            },
+        ),
+        Cmd::Doctor => {
+            let n_failed = doctor::doctor(&repo)?;
+            std::process::exit(n_failed.min(255) as i32);
+        }
+        Cmd::Export { output } => export::export(&repo, &output),
+        Cmd::Import { input } => export::import(&repo, &input),
+        Cmd::Gc => {
+            // TODO: Auto-checkpointing
+            let idx = get_idx(&repo)?;
+            let n = idx.compact(0.5)?;
+            println!("Dropped {} popular lines from the index", n);
+            // Start afresh, in case any of them are stale
+            idx.dedup_cache.clear()?;
+            idx.dedup_cache.flush()?;
+            Ok(())
+        }
+        Cmd::Prune { dry_run } => prune(&repo, dry_run),
+        Cmd::CompactDb {
+            dry_run,
+            compact_index,
+        } => compact_db(&db_path(&repo), dry_run, compact_index),
+        Cmd::RebuildIndex { verbose } => {
+            let time = std::time::Instant::now();
+            let idx = LineIdx::open(&db_path(&repo))?;
+            let (n_commits, n_lines) = idx.rebuild(&repo, verbose)?;
+            println!(
+                "Indexed {} lines from {} commits in {:.1?}",
+                n_lines,
+                n_commits,
+                time.elapsed()
+            );
+            Ok(())
+        }
+        Cmd::VerifyDb { fix } => {
+            let n_bad = LineIdx::open(&db_path(&repo))?.verify(fix)?;
+            if n_bad == 0 {
+                println!("The index is consistent");
+            } else if fix {
+                println!("Fixed {} problems", n_bad);
+            } else {
+                println!("Found {} problems; use --fix to remove them", n_bad);
+                std::process::exit(1);
+            }
+            Ok(())
+        }
+        Cmd::Fetch {
+            no_parallel,
+            merge_notes,
+            project,
+            closed,
+        } => {
+            if OPTS.offline {
+                println!("Offline mode: skipping fetch");
+                return Ok(());
+            }
+            fetch(&repo, no_parallel, project, closed)?;
+            if merge_notes {
+                fetch::merge_notes(&repo)?;
+            }
+            Ok(())
+        }
+        Cmd::FetchSingle { .. } if OPTS.offline => {
+            println!("Offline mode: skipping fetch");
+            Ok(())
+        }
+        Cmd::FetchSingle { id } => fetch::fetch_single(&repo, &id),
+        Cmd::Triage { count } => triage::triage(&repo, count.unwrap_or(5)),
+        Cmd::ReviewLoad { reviewer } => triage::review_load(&repo, reviewer),
+        Cmd::Mr { id } => merge_request(&repo, id),
+        Cmd::Pipeline { id } => pipeline(&repo, &id),
+        Cmd::MrBaseChange { .. } | Cmd::SyncApprovals { .. } if OPTS.offline => {
+            println!("Offline mode: skipping gitlab queries");
+            Ok(())
+        }
+        Cmd::MrBaseChange { id } => fetch::check_bases(&repo, id.as_deref()),
+        Cmd::SyncApprovals { id, dry_run } => sync_approvals(&repo, id.as_deref(), dry_run),
+        Cmd::MrApprovals { id } => mr_approvals(&repo, &id),
+        Cmd::DiffVersions { id, from, to } => diff_versions(&repo, id, from, to),
+        Cmd::MrDiff { id, version } => mr_diff(&repo, &id, version),
+        Cmd::MrCheckout { id, version } => mr_checkout(&repo, &id, version),
+        Cmd::MrPatch { id, output_dir } => mr_patch(&repo, &id, &output_dir),
+        Cmd::MrSize { id } => mr_size(&repo, &id),
+        Cmd::MrTimeline { id } => mr_timeline(&repo, &id),
+        Cmd::MrSquashPreview { diff, id } => mr_squash_preview(&repo, &id, diff),
+        Cmd::MrConflicts { id } => mr_conflicts(&repo, &id),
+        Cmd::MrComments { .. } if OPTS.offline => Err(anyhow!(
+            "Comments aren't cached, so they can't be shown offline"
+        )),
+        Cmd::MrComments {
+            unresolved_only,
+            id,
+        } => mr_comments(&repo, &id, unresolved_only),
+        Cmd::MrTracker { claim, release, id } => mr_tracker(&repo, &id, claim, release),
+        Cmd::MrLink {
+            branch_name,
+            force,
+            id,
+        } => mr_link(&repo, &id, branch_name, force),
+        Cmd::MrUnlink { id } => mr_unlink(&repo, &id),
+        Cmd::Mrs {
+            all,
+            sort,
+            label,
+            author,
+            target_branch,
+            since,
+        } => merge_requests(
+            &repo,
+            all,
+            sort.unwrap_or_default(),
+            label,
+            author,
+            target_branch,
+            since.as_deref().map(parse_since).transpose()?,
+        ),
+        Cmd::Remind { days, post_gitlab } => remind(&repo, days.unwrap_or(7), post_gitlab),
+        Cmd::Stale { days, include_own } => stale(&repo, days.unwrap_or(14), include_own),
+        Cmd::Nag { id, days, dry_run } => nag(&repo, id.as_deref(), days.unwrap_or(3), dry_run),
+        Cmd::MrAssign { .. } if OPTS.offline => {
+            Err(anyhow!("Can't change an MR's reviewers in offline mode"))
+        }
+        Cmd::MrAssign { role, id, username } => mr_assign(&repo, &id, &username, role),
+        Cmd::Completions { shell } => completions(&shell),
+        Cmd::Recent { by_reviewer } => {
+            if by_reviewer {
+                recent_by_reviewer(&repo)?;
+            } else {
+                for x in review_db::recent_notes(&repo)? {
+                    println!("{}", x);
+                }
+            }
+            let n_orphans = review_db::orphaned_notes(&repo)?.len();
+            if n_orphans > 0 {
+                eprintln!(
+                    "({} notes are attached to commits which no longer exist; see \"orpa orphans\")",
+                    n_orphans
+                );
+            }
             Ok(())
         }
+        Cmd::Orphans { clean } => orphans(&repo, clean),
+        Cmd::GcNotes { fix, dry_run } => gc_notes(&repo, fix && !dry_run),
+        Cmd::SearchNotes {
+            pattern,
+            case_insensitive,
+        } => search_notes(&repo, &pattern, case_insensitive),
         Cmd::Similar { revspec } => similar(&repo, &revspec),
+        Cmd::Blame { range } => blame(&repo, range),
+        Cmd::VerifySignatures { range } => verify_signatures(&repo, range),
+        Cmd::Audit { reviewer, since } => stats::audit(&repo, reviewer, since),
+        Cmd::Stats { range, author } => stats::stats(&repo, range, author),
+        Cmd::Compare { branch1, branch2 } => compare(&repo, &branch1, &branch2),
     }
 }
 
 fn load_watchlist(repo: &Repository) -> anyhow::Result<GlobSet> {
     use globset::*;
     let config = repo.config()?;
-    let globs = config.get_string("orpa.watchlist")?;
+    let globs = config::get_string(&config, "orpa.watchlist")?;
     let mut watchlist = GlobSetBuilder::new();
     for glob in globs.split(':') {
         watchlist.add(Glob::new(glob)?);
@@ -186,10 +1316,19 @@ fn load_watchlist(repo: &Repository) -> anyhow::Result<GlobSet> {
     Ok(watchlist.build()?)
 }
 
+/// Is the user an assignee or reviewer of the MR?
+fn is_assigned(mr: &MergeRequest, me: &str) -> bool {
+    mr.assignee
+        .iter()
+        .chain(mr.assignees.iter().flatten())
+        .chain(mr.reviewers.iter().flatten())
+        .any(|x| x.username == me)
+}
+
 fn summary(repo: &Repository) -> anyhow::Result<()> {
-    if let Ok(mrs) = cached_mrs(repo) {
+    if let Ok(mrs) = cached_open_mrs(repo) {
         let config = repo.config()?;
-        let me = config.get_string("gitlab.username")?;
+        let me = config::get_string(&config, "gitlab.username")?;
 
         let watchlist = load_watchlist(repo)?;
 
@@ -211,26 +1350,19 @@ fn summary(repo: &Repository) -> anyhow::Result<()> {
                 continue;
             }
             let mut f = || {
-                let (_, latest_rev) = versions
-                    .last_key_value()
-                    .ok_or_else(|| anyhow!("Can't find any versions"))?;
-                let n_unreviewed = version_stats(repo, latest_rev)?[Status::New];
+                let n_unreviewed = mr_unreviewed_count(repo, versions)?;
                 if n_unreviewed == 0 {
                     return Ok(());
                 }
+                let (_, latest_rev) = versions.last_key_value().unwrap();
 
-                let assigned = mr
-                    .assignee
-                    .iter()
-                    .chain(mr.assignees.iter().flatten())
-                    .chain(mr.reviewers.iter().flatten())
-                    .any(|x| x.username == me);
+                let assigned = is_assigned(mr, &me);
                 let watchlist_hit = mr_paths(repo, latest_rev)?
                     .iter()
                     .any(|path| watchlist.is_match(path));
                 let partially_reviewed = versions
-                    .iter()
-                    .flat_map(|(_, ver)| version_stats(repo, ver))
+                    .values()
+                    .flat_map(|ver| version_stats(repo, ver))
                     .any(|stats| stats[Status::Reviewed] > 0);
                 let is_interesting = assigned || watchlist_hit || partially_reviewed;
 
@@ -258,22 +1390,50 @@ fn summary(repo: &Repository) -> anyhow::Result<()> {
             }
         }
 
+        // If MRs are going into several branches, group them by target
+        let multi_target = mrs
+            .iter()
+            .filter(|x| x.mr.state == MergeRequestState::Opened)
+            .map(|x| &x.mr.target_branch)
+            .unique()
+            .count()
+            > 1;
+        if multi_target {
+            interesting.sort_by_key(|(mr, _)| &mr.target_branch);
+            recent.sort_by_key(|mr| &mr.target_branch);
+            own_recent.sort_by_key(|mr| &mr.target_branch);
+        }
+        let target_heading =
+            |tw: &mut TabWriter<_>, last: &mut Option<String>, mr: &MergeRequest| {
+                if multi_target && last.as_ref() != Some(&mr.target_branch) {
+                    writeln!(
+                        tw,
+                        "  {}",
+                        Paint::magenta(format!("→ {}", mr.target_branch))
+                    )?;
+                    *last = Some(mr.target_branch.clone());
+                }
+                std::io::Result::Ok(())
+            };
+
         if !interesting.is_empty() {
             println!("Relevant merge requests:");
             println!();
         }
         let mut tw = TabWriter::new(std::io::stdout()).ansi(true);
+        let mut last = None;
         for (mr, n_unreviewed) in &interesting {
+            target_heading(&mut tw, &mut last, mr)?;
             let when = timeago::Formatter::new().convert_chrono(mr.updated_at, chrono::Utc::now());
             writeln!(
                 tw,
-                "  {}{}\t{}\t{}\t{}\t({} left to review)",
-                Paint::yellow("!").bold(),
-                Paint::yellow(mr.iid.0).bold(),
+                "  {}\t{}\t{}\t{}\t({} left to review){}",
+                Paint::yellow(fmt_mr_ref(repo, mr)).bold(),
                 Paint::blue(&when).bold(),
                 Paint::green(&mr.author.username).bold(),
                 Paint::new(&mr.title).bold(),
                 Paint::new(n_unreviewed),
+                fmt_claim(mr),
             )?;
         }
         tw.flush()?;
@@ -286,16 +1446,18 @@ fn summary(repo: &Repository) -> anyhow::Result<()> {
             println!();
         }
         let mut tw = TabWriter::new(std::io::stdout()).ansi(true);
+        let mut last = None;
         for mr in &recent {
+            target_heading(&mut tw, &mut last, mr)?;
             let when = timeago::Formatter::new().convert_chrono(mr.updated_at, chrono::Utc::now());
             writeln!(
                 tw,
-                "  {}{}\t{}\t{}\t{}\t",
-                Paint::yellow("!"),
-                Paint::yellow(mr.iid.0),
+                "  {}\t{}\t{}\t{}\t{}",
+                Paint::yellow(fmt_mr_ref(repo, mr)),
                 Paint::blue(&when),
                 Paint::green(&mr.author.username).italic(),
                 &mr.title,
+                fmt_claim(mr),
             )?;
         }
         tw.flush()?;
@@ -321,13 +1483,14 @@ fn summary(repo: &Repository) -> anyhow::Result<()> {
             println!();
         }
         let mut tw = TabWriter::new(std::io::stdout()).ansi(true);
+        let mut last = None;
         for mr in &own_recent {
+            target_heading(&mut tw, &mut last, mr)?;
             let when = timeago::Formatter::new().convert_chrono(mr.updated_at, chrono::Utc::now());
             writeln!(
                 tw,
-                "  {}{}\t{}\t{}\t{}\t",
-                Paint::yellow("!"),
-                Paint::yellow(mr.iid.0),
+                "  {}\t{}\t{}\t{}\t",
+                Paint::yellow(fmt_mr_ref(repo, mr)),
                 Paint::blue(&when),
                 Paint::green(&mr.author.username).italic(),
                 &mr.title,
@@ -353,133 +1516,1998 @@ fn summary(repo: &Repository) -> anyhow::Result<()> {
     Ok(())
 }
 
-fn branch(repo: &Repository, range: Option<String>) -> anyhow::Result<()> {
-    let mut new = vec![];
-    walk_new(repo, range.as_ref(), |oid| new.push(oid))?;
-    let n_new = new.len();
-    let current = range.as_ref().map_or("Current branch", |x| x.as_str());
-    if n_new == 0 {
-        println!("{}: no unreviewed commits", current);
+fn branch(repo: &Repository, range: Option<String>) -> anyhow::Result<()> {
+    let mut new = vec![];
+    walk_new(repo, range.as_ref(), |oid| new.push(oid))?;
+    print_branch(repo, range.as_ref(), new)
+}
+
+fn print_branch(repo: &Repository, range: Option<&String>, new: Vec<Oid>) -> anyhow::Result<()> {
+    let n_new = new.len();
+    let current = range.map_or("Current branch", |x| x.as_str());
+    if n_new == 0 {
+        println!("{}: no unreviewed commits", current);
+    } else {
+        println!("{}: The following commits are awaiting review:\n", current);
+        for oid in new.into_iter().rev().take(10) {
+            show_commit_oneline(repo, oid)?;
+        }
+        let args = match range {
+            Some(r) => format!(" {}", r),
+            None => "".into(),
+        };
+        if n_new > 10 {
+            println!(
+                "  ...and {} more (use \"orpa list{}\" to see them)",
+                n_new - 10,
+                args,
+            );
+        }
+        if n_new > 20 {
+            println!("\nHint: That's a lot of unreviewed commits! You can skip old\nones by setting a checkpoint:    orpa checkpoint <oid>");
+        }
+    }
+    Ok(())
+}
+
+/// Re-run `branch` whenever HEAD or the notes move.  Runs until interrupted.
+fn watch_branch(
+    repo: &Repository,
+    range: Option<String>,
+    interval: Option<u64>,
+) -> anyhow::Result<()> {
+    let interval = match interval {
+        Some(x) => x.min(i64::MAX as u64) as i64,
+        None => repo.config()?.get_i64("orpa.watchInterval").unwrap_or(30),
+    };
+    // Anything less would spin, polling HEAD as fast as it can
+    if interval < 1 {
+        return Err(anyhow!(
+            "The watch interval must be at least 1 second, not {}",
+            interval
+        ));
+    }
+    let interval = std::time::Duration::from_secs(interval as u64);
+    let notes_ref = notes_ref().unwrap_or("refs/notes/commits");
+    let mut last_tips = None;
+    let mut last_new = None;
+    loop {
+        let head = repo.head()?.target();
+        let notes = repo.refname_to_id(notes_ref).ok();
+        if last_tips != Some((head, notes)) {
+            invalidate_reviews(repo);
+            let mut new = vec![];
+            walk_new(repo, range.as_ref(), |oid| new.push(oid))?;
+            if last_new.as_ref() != Some(&new) {
+                // Clear the screen and move the cursor to the top-left
+                print!("\x1b[2J\x1b[H");
+                print_branch(repo, range.as_ref(), new.clone())?;
+                std::io::stdout().flush()?;
+                last_new = Some(new);
+            }
+            last_tips = Some((head, notes));
+        }
+        std::thread::sleep(interval);
+    }
+}
+
+fn next(repo: &Repository, range: Option<String>, include_merges: bool) -> anyhow::Result<()> {
+    let mut last = None;
+    walk_new_with_opts(repo, range.as_ref(), include_merges, |oid, status| {
+        last = Some((oid, status))
+    })?;
+    match last {
+        Some((oid, status)) => {
+            if status == Status::Merge {
+                println!("{}", Paint::magenta("[merge]"));
+            }
+            show_commit_with_diffstat(repo, oid)?
+        }
+        None => println!("Everything looks good!"),
+    }
+    Ok(())
+}
+
+fn list(
+    repo: &Repository,
+    range: Option<String>,
+    include_merges: bool,
+    path: Option<String>,
+    format: &str,
+) -> anyhow::Result<()> {
+    let glob = path
+        .map(|x| anyhow::Ok(globset::Glob::new(&x)?.compile_matcher()))
+        .transpose()?;
+    let mut commits = vec![];
+    walk_new_with_opts(repo, range.as_ref(), include_merges, |oid, status| {
+        commits.push((oid, status))
+    })?;
+    for (oid, status) in commits {
+        if let Some(glob) = &glob {
+            let diff = commit_diff(repo, &repo.find_commit(oid)?)?;
+            let hit = diff.deltas().any(|delta| {
+                delta
+                    .old_file()
+                    .path()
+                    .into_iter()
+                    .chain(delta.new_file().path())
+                    .any(|path| glob.is_match(path))
+            });
+            if !hit {
+                continue;
+            }
+        }
+        let line = format_commit(repo, oid, format)?;
+        match status {
+            Status::Merge => println!("{} [merge]", line),
+            _ => println!("{}", line),
+        }
+    }
+    Ok(())
+}
+
+/// List the bookmarked commits in the range, with any notes left on them
+fn list_bookmarks(repo: &Repository, range: Option<String>) -> anyhow::Result<()> {
+    let mut commits = vec![];
+    walk_statuses(repo, range.as_ref(), |oid, status| {
+        if status == Status::Bookmarked {
+            commits.push(oid)
+        }
+    })?;
+    if commits.is_empty() {
+        println!("No bookmarked commits");
+        return Ok(());
+    }
+    for oid in commits {
+        let c = repo.find_commit(oid)?;
+        let short_id = c.as_object().short_id()?;
+        println!(
+            "{} {}",
+            Paint::yellow(short_id.as_str().unwrap_or("")),
+            c.summary().unwrap_or("")
+        );
+        let note = get_note(repo, oid)?.unwrap_or_default();
+        for line in note.lines().filter(|x| Trailer::parse(x).is_none()) {
+            println!("    {}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Fill in the placeholders in a `--format` template, git-log style.
+/// Anything which isn't a placeholder we know is printed as-is.
+fn format_commit(repo: &Repository, oid: Oid, template: &str) -> anyhow::Result<String> {
+    if template == "%H" {
+        // The common case; no need to look the commit up
+        return Ok(oid.to_string());
+    }
+    let commit = repo.find_commit(oid)?;
+    let author = commit.author();
+    let mut ret = String::new();
+    let mut rest = template;
+    while let Some(idx) = rest.find('%') {
+        ret.push_str(&rest[..idx]);
+        rest = &rest[idx..];
+        let (value, len) = match rest.get(1..3).unwrap_or("") {
+            "an" => (author.name().unwrap_or("").to_owned(), 3),
+            "ae" => (author.email().unwrap_or("").to_owned(), 3),
+            "ai" => (git_time_to_chrono(author.when()).to_rfc3339(), 3),
+            _ => match rest.get(1..2).unwrap_or("") {
+                "H" => (oid.to_string(), 2),
+                "h" => {
+                    let short_id = commit.as_object().short_id()?;
+                    (short_id.as_str().unwrap_or("").to_owned(), 2)
+                }
+                "s" => (commit.summary().unwrap_or("").to_owned(), 2),
+                "N" => (
+                    get_note(repo, oid)?.unwrap_or_default().lines().join(", "),
+                    2,
+                ),
+                _ => ("%".to_owned(), 1),
+            },
+        };
+        ret.push_str(&value);
+        rest = &rest[len..];
+    }
+    ret.push_str(rest);
+    Ok(ret)
+}
+
+fn diff_stat(repo: &Repository, range: Option<String>) -> anyhow::Result<()> {
+    let mut oids = vec![];
+    walk_new(repo, range.as_ref(), |oid| oids.push(oid))?;
+    // path => (insertions, deletions)
+    let mut files = HashMap::<PathBuf, (usize, usize)>::new();
+    for &oid in &oids {
+        let diff = commit_diff(repo, &repo.find_commit(oid)?)?;
+        for idx in 0..diff.deltas().len() {
+            let Some(patch) = git2::Patch::from_diff(&diff, idx)? else {
+                continue;
+            };
+            let delta = patch.delta();
+            let Some(path) = delta.new_file().path().or(delta.old_file().path()) else {
+                continue;
+            };
+            let (_, insertions, deletions) = patch.line_stats()?;
+            let x = files.entry(path.to_owned()).or_default();
+            x.0 += insertions;
+            x.1 += deletions;
+        }
+    }
+    let insertions: usize = files.values().map(|x| x.0).sum();
+    let deletions: usize = files.values().map(|x| x.1).sum();
+    println!(
+        "{} unreviewed commits, {} files changed, {} insertions(+), {} deletions(-)",
+        oids.len(),
+        files.len(),
+        Paint::green(insertions),
+        Paint::red(deletions),
+    );
+    let mut files = files.into_iter().collect::<Vec<_>>();
+    files.sort_by(|(p1, (i1, d1)), (p2, (i2, d2))| (i2 + d2).cmp(&(i1 + d1)).then(p1.cmp(p2)));
+    if !files.is_empty() {
+        println!();
+    }
+    let mut tw = TabWriter::new(std::io::stdout()).ansi(true);
+    for (path, (insertions, deletions)) in files.iter().take(10) {
+        writeln!(
+            tw,
+            "  {}\t{}\t{}",
+            path.display(),
+            Paint::green(format!("+{}", insertions)),
+            Paint::red(format!("-{}", deletions)),
+        )?;
+    }
+    tw.flush()?;
+    Ok(())
+}
+
+/// List the unreviewed commits which are only on one of the branches, and
+/// the ones which are on both
+fn compare(repo: &Repository, branch1: &str, branch2: &str) -> anyhow::Result<()> {
+    let mut only1 = vec![];
+    walk_new(repo, Some(&format!("{}..{}", branch2, branch1)), |oid| {
+        only1.push(oid)
+    })?;
+    let mut only2 = vec![];
+    walk_new(repo, Some(&format!("{}..{}", branch1, branch2)), |oid| {
+        only2.push(oid)
+    })?;
+    // Everything reachable from both branches is reachable from one of
+    // their merge bases
+    let tip1 = repo.revparse_single(branch1)?.peel_to_commit()?.id();
+    let tip2 = repo.revparse_single(branch2)?.peel_to_commit()?.id();
+    let mut walk = repo.revwalk()?;
+    for base in repo.merge_bases(tip1, tip2)?.iter() {
+        walk.push(*base)?;
+    }
+    let mut both = vec![];
+    for oid in walk {
+        let oid = oid?;
+        match lookup(repo, oid)? {
+            Status::New => both.push(oid),
+            Status::Checkpoint => break,
+            _ => (),
+        }
+    }
+
+    for (i, (title, oids)) in vec![
+        (format!("Only in {}", branch1), only1),
+        (format!("Only in {}", branch2), only2),
+        ("In both".to_owned(), both),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        if i > 0 {
+            println!();
+        }
+        println!("{} ({} unreviewed)", Paint::green(&title), oids.len());
+        for oid in oids {
+            show_commit_oneline(repo, oid)?;
+        }
+    }
+    Ok(())
+}
+
+fn list_by_author(repo: &Repository, range: Option<String>) -> anyhow::Result<()> {
+    let mut oids = vec![];
+    walk_new(repo, range.as_ref(), |oid| oids.push(oid))?;
+    let mut by_author = HashMap::<String, (String, Vec<Oid>)>::new();
+    for oid in oids {
+        let commit = repo.find_commit(oid)?;
+        let author = commit.author();
+        let email = author.email().unwrap_or("").to_owned();
+        by_author
+            .entry(email)
+            .or_insert_with(|| (author.name().unwrap_or("").to_owned(), vec![]))
+            .1
+            .push(oid);
+    }
+    let mut by_author = by_author.into_iter().collect::<Vec<_>>();
+    by_author.sort_by(|(e1, (_, x)), (e2, (_, y))| y.len().cmp(&x.len()).then(e1.cmp(e2)));
+    for (i, (email, (name, oids))) in by_author.into_iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        println!(
+            "{} <{}> ({} unreviewed)",
+            Paint::green(&name),
+            email,
+            oids.len()
+        );
+        for oid in oids {
+            show_commit_oneline(repo, oid)?;
+        }
+    }
+    Ok(())
+}
+
+fn list_by_mr(repo: &Repository, range: Option<String>) -> anyhow::Result<()> {
+    let mut oids = vec![];
+    walk_new(repo, range.as_ref(), |oid| oids.push(oid))?;
+    // If we've never fetched, everything is ungrouped
+    let mrs = cached_mrs(repo).unwrap_or_default();
+    let mut mr_commits = vec![];
+    for MRWithVersions { mr, versions } in &mrs {
+        let Some((_, latest)) = versions.last_key_value() else {
+            continue;
+        };
+        let mut walk = repo.revwalk()?;
+        if let Err(e) = walk.push_range(&format!("{}..{}", latest.base.0, latest.head.0)) {
+            error!("{}: {}", mr.iid.0, e);
+            continue;
+        }
+        let commits = walk.collect::<Result<HashSet<Oid>, _>>()?;
+        mr_commits.push((mr, commits));
+    }
+
+    let mut groups = vec![vec![]; mr_commits.len()];
+    let mut ungrouped = vec![];
+    for oid in oids {
+        match mr_commits.iter().position(|(_, x)| x.contains(&oid)) {
+            Some(i) => groups[i].push(oid),
+            None => ungrouped.push(oid),
+        }
+    }
+    let mut first = true;
+    let mut print_group = |header: String, oids: Vec<Oid>| -> anyhow::Result<()> {
+        if oids.is_empty() {
+            return Ok(());
+        }
+        if !first {
+            println!();
+        }
+        first = false;
+        println!("{} ({} unreviewed)", header, oids.len());
+        for oid in oids {
+            show_commit_oneline(repo, oid)?;
+        }
+        Ok(())
+    };
+    for ((mr, _), oids) in mr_commits.iter().zip(groups) {
+        let header = format!("{} {}", Paint::green(fmt_mr_ref(repo, mr)), mr.title);
+        print_group(header, oids)?;
+    }
+    print_group(Paint::yellow("ungrouped").to_string(), ungrouped)
+}
+
+fn files_changed(repo: &Repository, revspec: &str, count: bool) -> anyhow::Result<()> {
+    let commit = repo.revparse_single(revspec)?.peel_to_commit()?;
+    let mut diff = commit_diff(repo, &commit)?;
+    diff.find_similar(None)?;
+    if count {
+        println!("{}", diff.deltas().len());
+        return Ok(());
+    }
+    let mut files = diff
+        .deltas()
+        .map(|delta| {
+            let path = |x: git2::DiffFile| x.path().map(|x| x.display().to_string());
+            let old = path(delta.old_file()).unwrap_or_default();
+            let new = path(delta.new_file()).unwrap_or_else(|| old.clone());
+            let status = match delta.status() {
+                git2::Delta::Added => 'A',
+                git2::Delta::Deleted => 'D',
+                git2::Delta::Modified => 'M',
+                git2::Delta::Renamed => 'R',
+                git2::Delta::Copied => 'C',
+                git2::Delta::Typechange => 'T',
+                _ => '?',
+            };
+            (new, old, status)
+        })
+        .collect::<Vec<_>>();
+    files.sort();
+    for (new, old, status) in files {
+        if matches!(status, 'R' | 'C') {
+            println!("{} {} -> {}", status, old, new);
+        } else {
+            println!("{} {}", status, new);
+        }
+    }
+    Ok(())
+}
+
+fn explain(repo: &Repository, revspec: &str) -> anyhow::Result<()> {
+    let commit = repo.revparse_single(revspec)?.peel_to_commit()?;
+    let oid = commit.id();
+    let status = lookup(repo, oid)?;
+    println!("{} {} {:?}", revspec, oid, status);
+    println!();
+    let print_note = || -> anyhow::Result<()> {
+        let note = repo.find_note(notes_ref(), oid)?;
+        println!("It has a note (blob {}):", note.id());
+        println!();
+        for line in note.message().unwrap_or("").lines() {
+            println!("    {}", line);
+        }
+        for trailer in parse_trailers(note.message().unwrap_or("")) {
+            println!();
+            println!("    verb:  {}", trailer.verb);
+            println!("    name:  {}", trailer.name);
+            println!("    email: {}", trailer.email);
+        }
+        Ok(())
+    };
+    match status {
+        Status::Reviewed | Status::Skipped | Status::Bookmarked => print_note()?,
+        Status::Checkpoint => {
+            let note = repo.find_note(notes_ref(), oid)?;
+            println!(
+                "It has a checkpoint note (blob {}), so it and all its ancestors count as reviewed.",
+                note.id()
+            );
+        }
+        Status::Ours => {
+            let ours = our_email(repo);
+            if ours.iter().any(|x| x == commit.author().email_bytes()) {
+                let email = commit.author().email().unwrap_or("").to_owned();
+                println!(
+                    "It was authored by {}, which is one of your addresses:",
+                    email
+                );
+            } else {
+                let co_author = co_author_emails(&commit)
+                    .into_iter()
+                    .find(|x| ours.contains(x))
+                    .unwrap_or_default();
+                println!(
+                    "It lists {} as a co-author, which is one of your addresses:",
+                    String::from_utf8_lossy(&co_author)
+                );
+            }
+            for x in our_email(repo) {
+                println!("    {}", String::from_utf8_lossy(x));
+            }
+        }
+        Status::Merge => {
+            println!(
+                "It has {} parents, and merge commits don't need reviewing.",
+                commit.parent_count()
+            );
+        }
+        Status::New if !dedup_enabled() => {
+            println!(
+                "It doesn't have a note.  (Use --dedup to look for similar reviewed commits.)"
+            );
+        }
+        Status::New => {
+            let threshold = OPTS.dedup_threshold.unwrap_or(1.);
+            let digest = commit_diff_digest(repo, &commit)?;
+            let similar = similiar_commits(repo, &commit)?;
+            if similar.is_empty() {
+                println!("It doesn't have a note, and no reviewed commits are similar to it.");
+            } else {
+                println!(
+                    "It doesn't have a note, and no reviewed commits are similar enough \
+                     (threshold: {:.02}%):",
+                    threshold * 100.
+                );
+                println!();
+            }
+            for (other_oid, x) in similar.into_iter().take(10) {
+                let reason = if x.score() < threshold {
+                    "score too low"
+                } else if commit_diff_digest(repo, &repo.find_commit(other_oid)?)? != digest {
+                    "same lines, but the diffs differ"
+                } else {
+                    "identical"
+                };
+                println!("    {} {:.02}% ({})", other_oid, x.score() * 100., reason);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn verify_signatures(repo: &Repository, range: Option<String>) -> anyhow::Result<()> {
+    let mut walk = repo.revwalk()?;
+    match range {
+        Some(range) => walk.push_range(&range)?,
+        None => walk.push_head()?,
+    }
+    let mut tw = TabWriter::new(std::io::stdout()).ansi(true);
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        let check = check_signature(repo, commit.id())?;
+        let validity = match check.validity {
+            "good" => Paint::green(check.validity),
+            "no-sig" => Paint::new(check.validity),
+            _ => Paint::red(check.validity),
+        };
+        writeln!(
+            tw,
+            "{}\t{}\t{}\t{}\t{}",
+            Paint::yellow(commit.as_object().short_id()?.as_str().unwrap_or("")),
+            validity,
+            check.signer,
+            check.fingerprint,
+            commit.summary().unwrap_or(""),
+        )?;
+    }
+    tw.flush()?;
+    Ok(())
+}
+
+fn blame(repo: &Repository, range: Option<String>) -> anyhow::Result<()> {
+    let mut walk = repo.revwalk()?;
+    match range {
+        Some(range) => walk.push_range(&range)?,
+        None => walk.push_head()?,
+    }
+    let note_times = note_times(repo)?;
+    let mut tw = TabWriter::new(std::io::stdout()).ansi(true);
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        let short_id = commit.as_object().short_id()?;
+        let reviewers = get_note(repo, commit.id())?
+            .map(|note| {
+                parse_trailers(&note)
+                    .into_iter()
+                    .filter(|x| x.verb == "Reviewed")
+                    .map(|x| x.name)
+                    .unique()
+                    .join(", ")
+            })
+            .filter(|x| !x.is_empty());
+        let reviewed_at = note_times
+            .get(&commit.id())
+            .filter(|_| reviewers.is_some())
+            .map(|&x| git_time_to_chrono(x).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_default();
+        let reviewers = reviewers.unwrap_or_else(|| "—".into());
+        writeln!(
+            tw,
+            "{}\t{}\t{}\t{}\t{}",
+            Paint::yellow(short_id.as_str().unwrap_or("")),
+            commit.author().name().unwrap_or(""),
+            git_time_to_chrono(commit.time()).format("%Y-%m-%d %H:%M"),
+            Paint::green(reviewers),
+            Paint::blue(reviewed_at),
+        )?;
+    }
+    tw.flush()?;
+    Ok(())
+}
+
+fn show(repo: &Repository, revspec: &str) -> anyhow::Result<()> {
+    let oid = repo.revparse_single(revspec)?.peel_to_commit()?.id();
+    let status = lookup(repo, oid)?;
+    println!("{} {} {:?}", revspec, oid, status);
+    Ok(())
+}
+
+fn note_view(repo: &Repository, revspec: &str) -> anyhow::Result<()> {
+    let oid = repo.revparse_single(revspec)?.peel_to_commit()?.id();
+    let note = match repo.find_note(notes_ref(), oid) {
+        Ok(x) => x,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {
+            println!("No note found for {}", oid);
+            return Ok(());
+        }
+        Err(e) => return Err(e.into()),
+    };
+    let notes_ref = match notes_ref() {
+        Some(x) => x.to_owned(),
+        None => repo.note_default_ref()?,
+    };
+    println!("{} {}", Paint::yellow("note for"), Paint::yellow(oid));
+    println!("Ref:    {}", notes_ref);
+    if let Some(sig) = note_authors(repo)?.get(&oid) {
+        println!(
+            "Author: {} <{}>",
+            sig.name().unwrap_or(""),
+            sig.email().unwrap_or("")
+        );
+        let when = chrono::DateTime::from_timestamp(sig.when().seconds(), 0);
+        if let Some(when) = when {
+            println!("Date:   {}", when);
+        }
+    }
+    println!();
+    let trailer = regex::Regex::new("^[A-Za-z-]+: ")?;
+    for line in note.message().unwrap_or("").lines() {
+        if trailer.is_match(line) {
+            println!("    {}", Paint::green(line));
+        } else {
+            println!("    {}", line);
+        }
+    }
+    Ok(())
+}
+
+/// Let the user pick some unreviewed commits, and mark them all
+fn mark_interactive(repo: &Repository, verb: &str) -> anyhow::Result<()> {
+    let mut new = vec![];
+    walk_new(repo, None, |oid| new.push(oid))?;
+    if new.is_empty() {
+        println!("Everything looks good!");
+        return Ok(());
+    }
+    new.reverse();
+    let mut items = vec![];
+    for &oid in &new {
+        let c = repo.find_commit(oid)?;
+        items.push(format!(
+            "{} {}",
+            Paint::yellow(c.as_object().short_id()?.as_str().unwrap_or("")),
+            c.summary().unwrap_or(""),
+        ));
+    }
+    let selected = dialoguer::MultiSelect::new()
+        .with_prompt(format!("Select commits to mark as {}", verb))
+        .items(&items)
+        .interact()?;
+    for i in selected {
+        add_note(repo, new[i], verb)?;
+    }
+    Ok(())
+}
+
+fn add_note(repo: &Repository, oid: Oid, verb: &str) -> anyhow::Result<()> {
+    if let Ok(commit) = repo.find_commit(oid) {
+        if co_author_emails(&commit)
+            .iter()
+            .any(|x| our_email(repo).contains(x))
+        {
+            warn!("{}: You're listed as a co-author of this commit", oid);
+        }
+    }
+    append_note(repo, oid, &render_note(repo, oid, verb)?)
+}
+
+fn bookmark(repo: &Repository, oid: Oid, note: Option<&str>) -> anyhow::Result<()> {
+    let mut summary = merge_note(repo, oid, &render_note(repo, oid, "Bookmarked")?)?;
+    if let Some(note) = note {
+        summary = merge_note(repo, oid, note)?;
+    }
+    println!("{}: {}", oid, summary);
+    Ok(())
+}
+
+/// Remove the "Bookmarked-by" lines from a commit's note.  If that leaves no
+/// trailers, the rest of the note was only a reminder, so it goes too.
+fn unbookmark(repo: &Repository, oid: Oid) -> anyhow::Result<()> {
+    let note = get_note(repo, oid)?.unwrap_or_default();
+    let is_bookmark = |x: &str| Trailer::parse(x).is_some_and(|t| t.verb == "Bookmarked");
+    if !note.lines().any(is_bookmark) {
+        return Err(anyhow!("{} isn't bookmarked", oid));
+    }
+    let sig = repo.signature()?;
+    if is_bookmark_note(&note) {
+        repo.note_delete(oid, notes_ref(), &sig, &sig)?;
+        println!("{}: Removed the note", oid);
+    } else {
+        let rest = note.lines().filter(|x| !is_bookmark(x)).join("\n");
+        repo.note(&sig, &sig, notes_ref(), oid, &rest, true)?;
+        println!("{}: {}", oid, rest.lines().join(", "));
+    }
+    invalidate_reviews(repo);
+    Ok(())
+}
+
+fn mark_range(
+    repo: &Repository,
+    from: &str,
+    to: &str,
+    verb: &str,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let from = repo.revparse_single(from)?.peel_to_commit()?.id();
+    let to = repo.revparse_single(to)?.peel_to_commit()?.id();
+    if from == to {
+        println!("The range is empty");
+        return Ok(());
+    }
+    // Otherwise from..to would include commits from the other side of the
+    // fork, which is unlikely to be what was meant
+    if !repo.graph_descendant_of(to, from)? {
+        return Err(anyhow!("{} is not an ancestor of {}", from, to));
+    }
+    let mut walk = repo.revwalk()?;
+    walk.push(to)?;
+    walk.hide(from)?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+    let (mut n_marked, mut n_skipped) = (0, 0);
+    for oid in walk {
+        let oid = oid?;
+        if get_note(repo, oid)?.is_some() {
+            n_skipped += 1;
+            continue;
+        }
+        if dry_run {
+            let c = repo.find_commit(oid)?;
+            println!("Would mark {} {}", oid, c.summary().unwrap_or(""));
+        } else {
+            add_note(repo, oid, verb)?;
+        }
+        n_marked += 1;
+    }
+    if dry_run {
+        println!(
+            "Would mark {} commits, skipping {} already-marked",
+            n_marked, n_skipped
+        );
+    } else {
+        println!(
+            "Marked {} commits, skipped {} already-marked",
+            n_marked, n_skipped
+        );
+    }
+    Ok(())
+}
+
+/// The line to add to a commit's note when marking it with the given verb
+fn render_note(repo: &Repository, oid: Oid, verb: &str) -> anyhow::Result<String> {
+    let sig = repo.signature()?;
+    let new_note = match repo.config()?.get_string("orpa.noteTemplate") {
+        Ok(template) => {
+            let short_id = repo.find_object(oid, None)?.short_id()?;
+            let tokens = ["verb", "name", "email", "date", "oid"];
+            render_template(
+                "orpa.noteTemplate",
+                &template,
+                &tokens,
+                |token| match token {
+                    "verb" => Some(verb.to_owned()),
+                    "name" => Some(sig.name().unwrap_or("").to_owned()),
+                    "email" => Some(sig.email().unwrap_or("").to_owned()),
+                    "date" => Some(git_time_to_chrono(sig.when()).to_rfc3339()),
+                    "oid" => Some(short_id.as_str().unwrap_or("").to_owned()),
+                    _ => None,
+                },
+            )?
+        }
+        Err(_) => format!(
+            "{}-by: {} <{}>",
+            verb,
+            sig.name().unwrap_or(""),
+            sig.email().unwrap_or(""),
+        ),
+    };
+    Ok(new_note)
+}
+
+/// Replace each "{token}" in the template with its value.  The key is the
+/// git-config key the template came from, and is used in error messages.
+fn render_template(
+    key: &str,
+    template: &str,
+    tokens: &[&str],
+    lookup: impl Fn(&str) -> Option<String>,
+) -> anyhow::Result<String> {
+    let mut ret = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        ret.push_str(&rest[..start]);
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| anyhow!("{}: unterminated '{{' in \"{}\"", key, template))?;
+        let token = &rest[start + 1..start + end];
+        let value = lookup(token).ok_or_else(|| {
+            anyhow!(
+                "{}: unknown token \"{{{}}}\" (expected one of {})",
+                key,
+                token,
+                tokens.iter().map(|x| format!("{{{}}}", x)).join(", ")
+            )
+        })?;
+        ret.push_str(&value);
+        rest = &rest[start + end + 1..];
+    }
+    ret.push_str(rest);
+    Ok(ret)
+}
+
+pub struct GitlabConfig {
+    pub host: String,
+    pub project_id: ProjectId,
+    pub token: String,
+    /// How long to wait for a response to each API request
+    pub timeout: std::time::Duration,
+    /// Where the MRs for this project are cached
+    pub mr_dir: PathBuf,
+    /// Send API requests via this proxy
+    pub http_proxy: Option<String>,
+}
+
+impl GitlabConfig {
+    /// Load the config for every project we're tracking.  Projects are
+    /// listed with (possibly several) `orpa.project = <host>:<project_id>`
+    /// entries.  If there are none, we use gitlab.url and gitlab.projectId.
+    fn load_all(repo: &Repository) -> anyhow::Result<Vec<GitlabConfig>> {
+        info!("Loading the config");
+        let config = repo.config()?;
+        let token = config::get_string(&config, "gitlab.privateToken")?;
+        let timeout = std::time::Duration::from_secs(
+            config.get_i64("orpa.fetchTimeout").map_or(30, |x| x as u64),
+        );
+        let http_proxy = http_proxy(&config);
+        let mut projects = configured_projects(repo)?;
+        if projects.is_empty() {
+            let host =
+                config::get_string(&config, "gitlab.url").unwrap_or_else(|_| "gitlab.com".into());
+            let project_id = ProjectId(config::get_i64(&config, "gitlab.projectId")? as u64);
+            projects.push((host, project_id));
+        }
+        projects
+            .into_iter()
+            .map(|(host, project_id)| {
+                Ok(GitlabConfig {
+                    host,
+                    project_id,
+                    token: token.clone(),
+                    timeout,
+                    mr_dir: mr_dir(repo, project_id)?,
+                    http_proxy: http_proxy.clone(),
+                })
+            })
+            .collect()
+    }
+
+    /// A client for talking to the API directly
+    fn http_client(&self) -> anyhow::Result<reqwest::blocking::Client> {
+        http_client(self.timeout, self.http_proxy.as_deref())
+    }
+
+    /// Load the config for the project which the MR belongs to
+    fn for_project(repo: &Repository, project_id: ProjectId) -> anyhow::Result<GitlabConfig> {
+        GitlabConfig::load_all(repo)?
+            .into_iter()
+            .find(|x| x.project_id == project_id)
+            .ok_or_else(|| anyhow!("Project {} isn't configured", project_id.0))
+    }
+}
+
+/// `orpa.httpProxy`, or else `$HTTPS_PROXY`
+fn http_proxy(config: &git2::Config) -> Option<String> {
+    config
+        .get_string("orpa.httpProxy")
+        .ok()
+        .or_else(|| std::env::var("HTTPS_PROXY").ok())
+        .filter(|x| !x.is_empty())
+}
+
+fn http_client(
+    timeout: std::time::Duration,
+    proxy: Option<&str>,
+) -> anyhow::Result<reqwest::blocking::Client> {
+    let mut builder = reqwest::blocking::ClientBuilder::new().timeout(timeout);
+    if let Some(proxy) = proxy {
+        builder = builder
+            .proxy(reqwest::Proxy::https(proxy).map_err(|e| anyhow!("orpa.httpProxy: {}", e))?);
+    }
+    Ok(builder.build()?)
+}
+
+/// The projects listed in `orpa.project`, as (host, project ID)
+fn configured_projects(repo: &Repository) -> anyhow::Result<Vec<(String, ProjectId)>> {
+    let config = repo.config()?;
+    let mut ret = vec![];
+    let mut entries = config.multivar("orpa.project", None)?;
+    while let Some(entry) = entries.next() {
+        let entry = entry?;
+        let value = entry.value().unwrap_or("");
+        let (host, project_id) = value
+            .rsplit_once(':')
+            .and_then(|(host, id)| Some((host, id.parse().ok()?)))
+            .ok_or_else(|| {
+                anyhow!(
+                    "orpa.project: expected <host>:<project_id>, got \"{}\"",
+                    value
+                )
+            })?;
+        ret.push((host.to_owned(), ProjectId(project_id)));
+    }
+    Ok(ret)
+}
+
+fn db_path(repo: &Repository) -> PathBuf {
+    OPTS.db.clone().unwrap_or_else(|| repo.path().join("orpa"))
+}
+
+/// Where the MRs for the given project are cached.  If several projects are
+/// configured, each one gets its own subdirectory.
+fn mr_dir(repo: &Repository, project_id: ProjectId) -> anyhow::Result<PathBuf> {
+    let dir = db_path(repo).join("merge_requests");
+    if configured_projects(repo)?.is_empty() {
+        Ok(dir)
+    } else {
+        Ok(dir.join(project_id.0.to_string()))
+    }
+}
+
+/// Show which project an MR belongs to, if we're tracking more than one
+fn fmt_mr_ref(repo: &Repository, mr: &MergeRequest) -> String {
+    if configured_projects(repo).map_or(true, |x| x.is_empty()) {
+        format!("!{}", mr.iid.0)
+    } else {
+        format!("{}!{}", mr.project_id.0, mr.iid.0)
+    }
+}
+
+/// The paths of all cached MRs, across all projects
+fn cached_mr_files(repo: &Repository) -> anyhow::Result<Vec<PathBuf>> {
+    mr_files_under(&db_path(repo).join("merge_requests"))
+}
+
+fn mr_files_under(dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut ret = vec![];
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            for entry in std::fs::read_dir(entry.path())? {
+                ret.push(entry?.path());
+            }
+        } else {
+            ret.push(entry.path());
+        }
+    }
+    Ok(ret)
+}
+
+fn cached_mrs(repo: &Repository) -> anyhow::Result<Vec<MRWithVersions>> {
+    let mut mrs = vec![];
+    for path in cached_mr_files(repo)? {
+        let mr: MRWithVersions = serde_json::from_reader(File::open(path)?)?;
+        mrs.push(mr);
+    }
+    mrs.sort_by_key(|mr| std::cmp::Reverse(mr.mr.updated_at));
+    Ok(mrs)
+}
+
+/// The cached MRs which are still open
+fn cached_open_mrs(repo: &Repository) -> anyhow::Result<Vec<MRWithVersions>> {
+    let mut mrs = cached_mrs(repo)?;
+    mrs.retain(|x| {
+        matches!(
+            x.mr.state,
+            MergeRequestState::Opened | MergeRequestState::Reopened
+        )
+    });
+    Ok(mrs)
+}
+
+/// Load a single MR from the cache.  The target may be prefixed with a '!'.
+/// If several projects are being tracked, it may also be prefixed with the
+/// project ID, as in "1234!56".
+fn cached_mr(repo: &Repository, target: &str) -> anyhow::Result<MRWithVersions> {
+    let (project, iid) = match target.split_once('!') {
+        Some(("", iid)) => (None, iid),
+        Some((project, iid)) => (Some(project), iid),
+        None => (None, target),
+    };
+    let matches = cached_mr_files(repo)?
+        .into_iter()
+        .filter(|path| path.file_name() == Some(iid.as_ref()))
+        .filter(|path| {
+            project.is_none_or(|project| {
+                path.parent().and_then(|x| x.file_name()) == Some(project.as_ref())
+            })
+        })
+        .collect::<Vec<_>>();
+    match matches.as_slice() {
+        [] => Err(anyhow!("{}: No such MR in the cache", target)),
+        [path] => Ok(serde_json::from_reader(File::open(path)?)?),
+        _ => Err(anyhow!(
+            "{}: Ambiguous; several projects have this MR.  Use <project_id>!<id>",
+            target
+        )),
+    }
+}
+
+fn merge_request(repo: &Repository, target: String) -> anyhow::Result<()> {
+    pager::Pager::with_pager("less -FRSX").setup();
+    let MRWithVersions { mr, versions } = cached_mr(repo, &target)?;
+
+    let config = repo.config()?;
+    let me = config::get_string(&config, "gitlab.username")?;
+    print_mr(repo, &me, &mr);
+    println!();
+    for (&version, info) in &versions {
+        print_version(repo, version, info)?;
+    }
+    println!();
+    if let Some((_, version)) = versions.last_key_value() {
+        if let Ok((base, head)) = resolve_version(repo, version) {
+            let diff = repo.diff_tree_to_tree(Some(&base.tree()?), Some(&head.tree()?), None)?;
+            print_diff_stat(&diff)?;
+            println!();
+        }
+
+        let range = format!("{}..{}", &version.base.0, &version.head.0);
+        let mut walk = repo.revwalk()?;
+        walk.push_range(&range)?;
+        walk.set_sorting(git2::Sort::REVERSE)?;
+        for oid in walk {
+            let commit = repo.find_commit(oid?)?;
+            print_commit(commit);
+        }
+    }
+    Ok(())
+}
+
+fn pipeline(repo: &Repository, target: &str) -> anyhow::Result<()> {
+    let MRWithVersions { mr, .. } = cached_mr(repo, target)?;
+    let cached = if OPTS.offline { " [cached data]" } else { "" };
+    let Some(pipeline) = mr.pipeline else {
+        println!("No pipeline found{}", cached);
+        return Ok(());
+    };
+    let latest = match (&pipeline.sha, &mr.sha) {
+        (Some(x), Some(y)) if x == y => " (latest)",
+        (Some(_), Some(_)) => " (not for the latest version)",
+        _ => "",
+    };
+    println!(
+        "Pipeline #{}: {} {}{}{}",
+        pipeline.id,
+        fmt_pipeline_status(&pipeline.status),
+        pipeline.status,
+        latest,
+        cached,
+    );
+    println!("URL:     {}", pipeline.web_url);
+    if let Some(x) = pipeline.created_at {
+        println!("Created: {}", x);
+    }
+    Ok(())
+}
+
+fn mr_approvals(repo: &Repository, target: &str) -> anyhow::Result<()> {
+    let MRWithVersions { mr, versions } = cached_mr(repo, target)?;
+    // Approvals aren't cached, but we can still show the local reviews
+    let approvals = if OPTS.offline {
+        None
+    } else {
+        let config = GitlabConfig::for_project(repo, mr.project_id)?;
+        Some(fetch::query_approvals(&config, mr.iid)?)
+    };
+    let fmt_user = |x: &fetch::UserBasic| format!("{} (@{})", x.name, x.username);
+    match &approvals {
+        None => println!(
+            "{}: approval status unknown [cached data]",
+            fmt_mr_ref(repo, &mr)
+        ),
+        Some(approvals) => {
+            println!(
+                "{}: {}",
+                fmt_mr_ref(repo, &mr),
+                if approvals.approved {
+                    Paint::green("approved")
+                } else {
+                    Paint::yellow("not approved")
+                }
+            );
+            println!();
+            println!("Approved by:");
+            if approvals.approved_by.is_empty() {
+                println!("    nobody");
+            }
+            for x in &approvals.approved_by {
+                println!("    {}", fmt_user(&x.user));
+            }
+            if !approvals.approvers_left.is_empty() {
+                println!("Waiting for:");
+                for x in &approvals.approvers_left {
+                    println!("    {}", fmt_user(x));
+                }
+            }
+        }
+    }
+
+    // Now see who has reviewed the latest version locally without approving
+    let Some((_, latest)) = versions.last_key_value() else {
+        return Ok(());
+    };
+    let mut reviewers = HashMap::<String, usize>::new();
+    for x in walk_version(repo, latest)? {
+        let (oid, _) = x?;
+        let note = get_note(repo, oid)?.unwrap_or_default();
+        for trailer in parse_trailers(&note) {
+            if trailer.verb == "Reviewed" {
+                *reviewers.entry(trailer.name).or_default() += 1;
+            }
+        }
+    }
+    let approved_names = approvals
+        .iter()
+        .flat_map(|x| &x.approved_by)
+        .flat_map(|x| [&x.user.name, &x.user.username])
+        .collect::<HashSet<_>>();
+    let unapproved = reviewers
+        .into_iter()
+        .filter(|(name, _)| !approved_names.contains(name))
+        .sorted()
+        .collect::<Vec<_>>();
+    if !unapproved.is_empty() {
+        if approvals.is_some() {
+            println!("Reviewed locally, but not approved:");
+        } else {
+            println!("Reviewed locally:");
+        }
+        for (name, n) in unapproved {
+            println!("    {} ({} commits)", name, n);
+        }
+    }
+    Ok(())
+}
+
+fn sync_approvals(repo: &Repository, target: Option<&str>, dry_run: bool) -> anyhow::Result<()> {
+    let mrs = match target {
+        Some(x) => vec![cached_mr(repo, x)?],
+        None => cached_open_mrs(repo)?,
+    };
+    for MRWithVersions { mr, versions } in mrs {
+        let Some((_, latest)) = versions.last_key_value() else {
+            continue;
+        };
+        let config = GitlabConfig::for_project(repo, mr.project_id)?;
+        let approvals = match fetch::query_approvals(&config, mr.iid) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("{}: {}", fmt_mr_ref(repo, &mr), e);
+                continue;
+            }
+        };
+        if approvals.approved_by.is_empty() {
+            continue;
+        }
+        let mut oids = vec![];
+        for x in walk_version(repo, latest)? {
+            oids.push(x?.0);
+        }
+        for approver in &approvals.approved_by {
+            // Gitlab doesn't tell us people's email addresses
+            let user = &approver.user;
+            let note = format!(
+                "Reviewed-by: {} <{}@{}>",
+                user.name, user.username, config.host
+            );
+            for &oid in &oids {
+                if dry_run {
+                    println!("Would add \"{}\" to {}", note, oid);
+                } else {
+                    append_note(repo, oid, &note)?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn diff_versions(
+    repo: &Repository,
+    target: String,
+    from: Option<u16>,
+    to: Option<u16>,
+) -> anyhow::Result<()> {
+    let MRWithVersions { mr, versions } = cached_mr(repo, &target)?;
+    // The user counts versions from 1
+    let from_user = |x: u16| {
+        x.checked_sub(1)
+            .map(Version)
+            .ok_or_else(|| anyhow!("Versions are numbered from 1"))
+    };
+    let to = match to {
+        Some(x) => from_user(x)?,
+        None => *versions
+            .keys()
+            .last()
+            .ok_or_else(|| anyhow!("!{} has no versions", mr.iid.0))?,
+    };
+    let from = match from {
+        Some(x) => from_user(x)?,
+        None => {
+            *versions
+                .range(..to)
+                .last()
+                .ok_or_else(|| anyhow!("!{} has no version before {}", mr.iid.0, to))?
+                .0
+        }
+    };
+    let lookup_version = |v: Version| {
+        versions
+            .get(&v)
+            .ok_or_else(|| anyhow!("!{} has no version {}", mr.iid.0, v))
+    };
+    let (_, old_head) = resolve_version(repo, lookup_version(from)?)?;
+    let (_, new_head) = resolve_version(repo, lookup_version(to)?)?;
+
+    pager::Pager::with_pager("less -FRSX").setup();
+    println!(
+        "{} {}..{}",
+        Paint::yellow(format!("merge_request {}", fmt_mr_ref(repo, &mr))),
+        from,
+        to,
+    );
+    println!();
+    let diff = repo.diff_tree_to_tree(Some(&old_head.tree()?), Some(&new_head.tree()?), None)?;
+    print_diff_stat(&diff)?;
+    println!();
+    print_patch(&diff)?;
+    Ok(())
+}
+
+/// Delete the version refs created by `fetch` for MRs which are no longer
+/// open.  Refs for MRs which aren't in the cache are left alone.
+fn prune(repo: &Repository, dry_run: bool) -> anyhow::Result<()> {
+    // The refs are named "refs/orpa/<project_id>/<iid>_<source_branch>/<version>".
+    // Older versions of orpa didn't include the project.
+    let states = cached_mrs(repo)?
+        .into_iter()
+        .map(|x| {
+            (
+                (x.mr.project_id.0, x.mr.iid.0, x.mr.source_branch),
+                x.mr.state,
+            )
+        })
+        .collect::<HashMap<_, _>>();
+    let mut n_pruned = 0;
+    for reference in repo.references_glob("refs/orpa/*")? {
+        let mut reference = reference?;
+        let Some(name) = reference.name().map(|x| x.to_owned()) else {
+            continue;
+        };
+        let path = name.trim_start_matches("refs/orpa/");
+        let (project, path) = match path.split_once('/') {
+            Some((project, rest)) => match project.parse::<u64>() {
+                Ok(project) => (Some(project), rest),
+                Err(_) => (None, path),
+            },
+            None => (None, path),
+        };
+        let key = path
+            .rsplit_once('/')
+            .and_then(|(x, _)| x.split_once('_'))
+            .and_then(|(iid, branch)| Some((iid.parse::<u64>().ok()?, branch)));
+        let Some((iid, branch)) = key else {
+            warn!("{}: Not created by orpa; leaving it alone", name);
+            continue;
+        };
+        let closed = |x: &MergeRequestState| {
+            matches!(x, MergeRequestState::Closed | MergeRequestState::Merged)
+        };
+        let state = match project {
+            Some(project) => states.get(&(project, iid, branch.to_owned())).copied(),
+            // We don't know which project an old ref belongs to, so only
+            // delete it if every MR it could belong to is finished
+            None => {
+                let candidates = states
+                    .iter()
+                    .filter(|((_, x, y), _)| *x == iid && y == branch)
+                    .map(|(_, state)| *state)
+                    .collect::<Vec<_>>();
+                candidates
+                    .iter()
+                    .all(closed)
+                    .then(|| candidates.first().copied())
+                    .flatten()
+            }
+        };
+        let Some(state) = state.filter(closed) else {
+            continue;
+        };
+        if dry_run {
+            println!("Would delete {} (!{} {})", name, iid, fmt_state(state));
+        } else {
+            reference.delete()?;
+            println!("Deleted {} (!{} {})", name, iid, fmt_state(state));
+        }
+        n_pruned += 1;
+    }
+    if n_pruned == 0 {
+        println!("Nothing to prune");
+    }
+    Ok(())
+}
+
+fn mr_assign(
+    repo: &Repository,
+    target: &str,
+    username: &str,
+    role: AssignRole,
+) -> anyhow::Result<()> {
+    let MRWithVersions { mr, versions } = cached_mr(repo, target)?;
+    let config = GitlabConfig::for_project(repo, mr.project_id)?;
+    let gl = fetch::connect(&config)?;
+    let username = username.trim_start_matches('@');
+    let (name, updated) = fetch::add_to_mr(&gl, &config, mr.iid, username, role)?;
+    println!(
+        "Added {} (@{}) as {} of {}",
+        name,
+        username,
+        match role {
+            AssignRole::Reviewer => "a reviewer",
+            AssignRole::Assignee => "an assignee",
+        },
+        fmt_mr_ref(repo, &mr)
+    );
+    // Gitlab's answer doesn't include the things we add ourselves
+    let mr = MergeRequest {
+        pipeline: mr.pipeline,
+        reviewer_claim: mr.reviewer_claim,
+        ..updated
+    };
+    let path = mr_dir(repo, mr.project_id)?.join(mr.iid.0.to_string());
+    serde_json::to_writer(File::create(path)?, &MRWithVersions { mr, versions })?;
+    Ok(())
+}
+
+fn mr_tracker(repo: &Repository, target: &str, claim: bool, release: bool) -> anyhow::Result<()> {
+    let MRWithVersions { mut mr, versions } = cached_mr(repo, target)?;
+    let mr_ref = fmt_mr_ref(repo, &mr);
+    if claim && release {
+        return Err(anyhow!("--claim and --release can't be used together"));
+    }
+    if claim {
+        let me = config::get_string(&repo.config()?, "gitlab.username")?;
+        match &mr.reviewer_claim {
+            Some(x) if *x == me => (),
+            Some(x) => println!("Taking over {} from @{}", mr_ref, x),
+            None => (),
+        }
+        mr.reviewer_claim = Some(me);
+    } else if release {
+        mr.reviewer_claim = None;
+    }
+    match &mr.reviewer_claim {
+        Some(x) => println!("{} is claimed by @{}", mr_ref, x),
+        None => println!("{} isn't claimed by anyone", mr_ref),
+    }
+    if claim || release {
+        let path = mr_dir(repo, mr.project_id)?.join(mr.iid.0.to_string());
+        serde_json::to_writer(File::create(path)?, &MRWithVersions { mr, versions })?;
+    }
+    Ok(())
+}
+
+/// "[claimed by @alice]", if someone has claimed the MR
+fn fmt_claim(mr: &MergeRequest) -> String {
+    match &mr.reviewer_claim {
+        Some(x) => format!(" {}", Paint::cyan(format!("[claimed by @{}]", x))),
+        None => String::new(),
+    }
+}
+
+fn mr_branch_name(mr: &MergeRequest) -> String {
+    format!("mr/{}", mr.iid.0)
+}
+
+fn mr_link(
+    repo: &Repository,
+    target: &str,
+    name: Option<String>,
+    force: bool,
+) -> anyhow::Result<()> {
+    let MRWithVersions { mr, versions } = cached_mr(repo, target)?;
+    let (version, info) = versions
+        .last_key_value()
+        .ok_or_else(|| anyhow!("!{} has no versions", mr.iid.0))?;
+    let commit = repo
+        .find_commit(info.head.as_oid())
+        .map_err(|e| anyhow!("{} (try running git fetch)", e))?;
+    let name = name.unwrap_or_else(|| mr_branch_name(&mr));
+    repo.branch(&name, &commit, force)
+        .map_err(|e| match e.code() {
+            git2::ErrorCode::Exists => anyhow!("{} already exists; use --force to move it", name),
+            _ => e.into(),
+        })?;
+    println!(
+        "{} now points at {} ({})",
+        name,
+        &info.head.0[..10],
+        version
+    );
+    Ok(())
+}
+
+fn mr_unlink(repo: &Repository, target: &str) -> anyhow::Result<()> {
+    let MRWithVersions { mr, .. } = cached_mr(repo, target)?;
+    let name = mr_branch_name(&mr);
+    let mut branch = repo
+        .find_branch(&name, git2::BranchType::Local)
+        .map_err(|_| anyhow!("{}: No such branch", name))?;
+    branch.delete()?;
+    println!("Deleted {}", name);
+    Ok(())
+}
+
+fn mr_comments(repo: &Repository, target: &str, unresolved_only: bool) -> anyhow::Result<()> {
+    let MRWithVersions { mr, .. } = cached_mr(repo, target)?;
+    let config = GitlabConfig::for_project(repo, mr.project_id)?;
+    let mut discussions = fetch::query_discussions(&config, mr.iid)?;
+    for x in &mut discussions {
+        x.notes.retain(|note| !note.system);
+    }
+    discussions.retain(|x| !x.notes.is_empty());
+    if unresolved_only {
+        discussions.retain(|x| !x.resolved());
+    }
+    if discussions.is_empty() {
+        println!("{}: no comments", fmt_mr_ref(repo, &mr));
+        return Ok(());
+    }
+
+    pager::Pager::with_pager("less -FRSX").setup();
+    let now = chrono::Utc::now();
+    for (i, discussion) in discussions.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let status = match (discussion.notes[0].resolvable, discussion.resolved()) {
+            (false, _) => Paint::new(""),
+            (true, true) => Paint::green(" (resolved)"),
+            (true, false) => Paint::yellow(" (unresolved)"),
+        };
+        let position = discussion.notes[0].position.as_ref().and_then(|x| {
+            let path = x.new_path.as_ref().or(x.old_path.as_ref())?;
+            Some(match x.new_line.or(x.old_line) {
+                Some(line) => format!(" {}:{}", path, line),
+                None => format!(" {}", path),
+            })
+        });
+        println!(
+            "{}{}{}",
+            Paint::yellow(format!(
+                "thread {}",
+                discussion.id.get(..8).unwrap_or(&discussion.id)
+            )),
+            Paint::cyan(position.unwrap_or_default()),
+            status
+        );
+        for note in &discussion.notes {
+            println!();
+            println!(
+                "  {} (@{}), {}:",
+                note.author.name,
+                Paint::green(&note.author.username),
+                Paint::blue(timeago::Formatter::new().convert_chrono(note.created_at, now))
+            );
+            for line in note.body.lines() {
+                println!("      {}", line);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The head of the branch in one of the remotes or, failing that, the
+/// local branch of the same name
+fn local_branch_head<'repo>(
+    repo: &'repo Repository,
+    branch: &str,
+) -> anyhow::Result<Option<Commit<'repo>>> {
+    let remotes = repo.remotes()?;
+    let candidates = remotes
+        .iter()
+        .flatten()
+        .map(|remote| format!("refs/remotes/{}/{}", remote, branch))
+        .chain(std::iter::once(format!("refs/heads/{}", branch)));
+    for name in candidates {
+        if let Ok(x) = repo.find_reference(&name) {
+            return Ok(Some(x.peel_to_commit()?));
+        }
+    }
+    Ok(None)
+}
+
+fn mr_conflicts(repo: &Repository, target: &str) -> anyhow::Result<()> {
+    let MRWithVersions { mr, versions } = cached_mr(repo, target)?;
+    let mr_ref = fmt_mr_ref(repo, &mr);
+    let (_, info) = versions
+        .last_key_value()
+        .ok_or_else(|| anyhow!("{} has no versions", mr_ref))?;
+    let head = repo
+        .find_commit(info.head.as_oid())
+        .map_err(|e| anyhow!("{} (try running git fetch)", e))?;
+    let Some(target_head) = local_branch_head(repo, &mr.target_branch)? else {
+        return Err(anyhow!(
+            "Can't find {} locally (try running git fetch)",
+            mr.target_branch
+        ));
+    };
+    let index = repo.merge_commits(&target_head, &head, None)?;
+    let mut paths = vec![];
+    for conflict in index.conflicts()? {
+        let conflict = conflict?;
+        let entry = conflict
+            .our
+            .as_ref()
+            .or(conflict.their.as_ref())
+            .or(conflict.ancestor.as_ref());
+        if let Some(entry) = entry {
+            paths.push(String::from_utf8_lossy(&entry.path).into_owned());
+        }
+    }
+    if paths.is_empty() {
+        println!("{} merges cleanly into {}", mr_ref, mr.target_branch);
+        if mr.has_conflicts == Some(true) {
+            println!("(gitlab disagrees; your copy of the branches may be out of date)");
+        }
+        return Ok(());
+    }
+    println!("{} conflicts with {} in:", mr_ref, mr.target_branch);
+    println!();
+    for path in paths {
+        println!("  {}", Paint::red(path));
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum EventKind {
+    Opened,
+    /// A new version
+    Pushed,
+    /// A local note
+    Reviewed,
+    Approved,
+    Closed,
+    Reopened,
+    Merged,
+    Commented,
+}
+
+impl EventKind {
+    fn label(self) -> Paint<&'static str> {
+        match self {
+            EventKind::Opened => Paint::blue("opened"),
+            EventKind::Pushed => Paint::yellow("pushed"),
+            EventKind::Reviewed => Paint::cyan("reviewed"),
+            EventKind::Approved => Paint::green("approved"),
+            EventKind::Closed => Paint::red("closed"),
+            EventKind::Reopened => Paint::blue("reopened"),
+            EventKind::Merged => Paint::magenta("merged"),
+            EventKind::Commented => Paint::new("commented"),
+        }
+    }
+}
+
+/// Something which happened to an MR, either on gitlab or locally
+struct Event {
+    timestamp: chrono::DateTime<chrono::Utc>,
+    kind: EventKind,
+    author: String,
+    body: String,
+}
+
+fn mr_timeline(repo: &Repository, target: &str) -> anyhow::Result<()> {
+    let MRWithVersions { mr, versions } = cached_mr(repo, target)?;
+    let mut events = vec![];
+    if let Some(created_at) = mr.created_at {
+        events.push(Event {
+            timestamp: created_at,
+            kind: EventKind::Opened,
+            author: format!("@{}", mr.author.username),
+            body: mr.title.clone(),
+        });
+    }
+    // Gitlab's system notes say when commits were added, but the versions
+    // are what the rest of orpa talks about
+    for (version, info) in &versions {
+        if let Some(created_at) = info.created_at {
+            events.push(Event {
+                timestamp: created_at,
+                kind: EventKind::Pushed,
+                author: String::new(),
+                body: format!("{} {}", version, info),
+            });
+        }
+    }
+
+    let note_authors = note_authors(repo)?;
+    let mut seen = HashSet::new();
+    for info in versions.values() {
+        let Ok(walk) = walk_version(repo, info) else {
+            continue;
+        };
+        for x in walk {
+            let (oid, _) = x?;
+            if !seen.insert(oid) {
+                continue;
+            }
+            let Some(sig) = note_authors.get(&oid) else {
+                continue;
+            };
+            let commit = repo.find_commit(oid)?;
+            events.push(Event {
+                timestamp: git_time_to_chrono(sig.when()).with_timezone(&chrono::Utc),
+                kind: EventKind::Reviewed,
+                author: sig.name().unwrap_or("").to_owned(),
+                body: format!(
+                    "{} {}",
+                    commit.as_object().short_id()?.as_str().unwrap_or(""),
+                    commit.summary().unwrap_or("")
+                ),
+            });
+        }
+    }
+
+    if OPTS.offline {
+        println!("Only showing local events [cached data]");
+        println!();
+    } else {
+        let config = GitlabConfig::for_project(repo, mr.project_id)?;
+        for x in fetch::query_state_events(&config, mr.iid)? {
+            let kind = match x.state.as_str() {
+                "closed" => EventKind::Closed,
+                "reopened" => EventKind::Reopened,
+                "merged" => EventKind::Merged,
+                _ => continue,
+            };
+            events.push(Event {
+                timestamp: x.created_at,
+                kind,
+                author: x.user.map_or(String::new(), |x| format!("@{}", x.username)),
+                body: String::new(),
+            });
+        }
+        for note in fetch::query_notes(&config, mr.iid)? {
+            let kind = if !note.system {
+                EventKind::Commented
+            } else if note.body.starts_with("approved this merge request") {
+                EventKind::Approved
+            } else {
+                // Pushes are covered by the versions, and state changes by
+                // the state events
+                continue;
+            };
+            events.push(Event {
+                timestamp: note.created_at,
+                kind,
+                author: format!("@{}", note.author.username),
+                body: if note.system {
+                    String::new()
+                } else {
+                    note.body.lines().next().unwrap_or("").to_owned()
+                },
+            });
+        }
+    }
+    // Stable, so simultaneous events stay in the order above
+    events.sort_by_key(|x| x.timestamp);
+
+    println!(
+        "{} {}",
+        Paint::yellow(format!("merge_request {}", fmt_mr_ref(repo, &mr))),
+        mr.title
+    );
+    println!();
+    let mut tw = TabWriter::new(std::io::stdout()).ansi(true);
+    for x in events {
+        writeln!(
+            tw,
+            "  {}\t{}\t{}\t{}",
+            x.timestamp.format("%Y-%m-%d %H:%M"),
+            x.kind.label(),
+            Paint::green(x.author),
+            x.body
+        )?;
+    }
+    tw.flush()?;
+    Ok(())
+}
+
+/// Lines which take about a minute to review
+const LINES_PER_MINUTE: usize = 40;
+
+/// What sort of file a path is, going by its name
+fn file_kind(path: &Path) -> &'static str {
+    let name = path.file_name().and_then(|x| x.to_str()).unwrap_or("");
+    let ext = path.extension().and_then(|x| x.to_str()).unwrap_or("");
+    let in_dir = |dir: &str| path.components().any(|x| x.as_os_str() == dir);
+    if name.ends_with(".lock")
+        || name == "package-lock.json"
+        || name.contains(".min.")
+        || name.contains("generated")
+        || ext == "snap"
+    {
+        "generated"
+    } else if in_dir("test")
+        || in_dir("tests")
+        || name.starts_with("test_")
+        || ["_test.", ".test.", "_spec.", ".spec."]
+            .iter()
+            .any(|x| name.contains(x))
+    {
+        "tests"
+    } else if in_dir("doc") || in_dir("docs") || matches!(ext, "md" | "rst" | "txt" | "adoc") {
+        "docs"
     } else {
-        println!("{}: The following commits are awaiting review:\n", current);
-        for oid in new.into_iter().rev().take(10) {
-            show_commit_oneline(repo, oid)?;
+        "code"
+    }
+}
+
+fn mr_size(repo: &Repository, target: &str) -> anyhow::Result<()> {
+    let config = repo.config()?;
+    let buckets = match config::get_string(&config, "orpa.sizeBuckets") {
+        Ok(x) => {
+            let bounds = x
+                .split(',')
+                .map(|x| x.trim().parse::<usize>())
+                .collect::<Result<Vec<_>, _>>()
+                .ok()
+                .filter(|xs| xs.len() == 4 && xs.windows(2).all(|w| w[0] < w[1]));
+            bounds.ok_or_else(|| {
+                anyhow!(
+                    "orpa.sizeBuckets: expected four increasing numbers, eg. \
+                     \"50,200,500,1500\", got \"{}\"",
+                    x
+                )
+            })?
         }
-        let args = match range.as_ref() {
-            Some(r) => format!(" {}", r),
-            None => "".into(),
+        Err(_) => vec![50, 200, 500, 1500],
+    };
+
+    let MRWithVersions { mr, versions } = cached_mr(repo, target)?;
+    let (version, info) = versions
+        .last_key_value()
+        .ok_or_else(|| anyhow!("!{} has no versions", mr.iid.0))?;
+    let (base, head) = resolve_version(repo, info)?;
+    // Diff the trees rather than adding up the commits, so the size doesn't
+    // depend on what's been reviewed or on merges from the target branch
+    let diff = repo.diff_tree_to_tree(Some(&base.tree()?), Some(&head.tree()?), None)?;
+    let mut by_kind = BTreeMap::<&str, (usize, usize)>::new();
+    for i in 0..diff.deltas().len() {
+        let Some(patch) = git2::Patch::from_diff(&diff, i)? else {
+            continue;
         };
-        if n_new > 10 {
-            println!(
-                "  ...and {} more (use \"orpa list{}\" to see them)",
-                n_new - 10,
-                args,
-            );
-        }
-        if n_new > 20 {
-            println!("\nHint: That's a lot of unreviewed commits! You can skip old\nones by setting a checkpoint:    orpa checkpoint <oid>");
-        }
+        let delta = patch.delta();
+        let Some(path) = delta.new_file().path().or(delta.old_file().path()) else {
+            continue;
+        };
+        let (_, added, deleted) = patch.line_stats()?;
+        let entry = by_kind.entry(file_kind(path)).or_default();
+        entry.0 += added;
+        entry.1 += deleted;
     }
-    Ok(())
-}
+    let n_lines: usize = by_kind.values().map(|(x, y)| x + y).sum();
+    let bucket = ["XS", "S", "M", "L"]
+        .iter()
+        .zip(&buckets)
+        .find(|(_, &bound)| n_lines < bound)
+        .map_or("XL", |(x, _)| *x);
+    let minutes = n_lines / LINES_PER_MINUTE;
+    let time = if minutes == 0 {
+        "under a minute".to_owned()
+    } else if minutes < 60 {
+        format!("about {} min", minutes)
+    } else {
+        format!("about {} h {} min", minutes / 60, minutes % 60)
+    };
 
-fn next(repo: &Repository, range: Option<String>) -> anyhow::Result<()> {
-    let mut last = None;
-    walk_new(repo, range.as_ref(), |oid| last = Some(oid))?;
-    match last {
-        Some(oid) => show_commit_with_diffstat(repo, oid)?,
-        None => println!("Everything looks good!"),
+    println!(
+        "{} {}: {} ({} lines changed, {} to review)",
+        fmt_mr_ref(repo, &mr),
+        version,
+        Paint::new(bucket).bold(),
+        n_lines,
+        time
+    );
+    if !by_kind.is_empty() {
+        println!();
+        let mut tw = TabWriter::new(std::io::stdout()).ansi(true);
+        for (kind, (added, deleted)) in by_kind {
+            writeln!(
+                tw,
+                "  {}\t{}\t{}",
+                kind,
+                Paint::green(format!("+{}", added)),
+                Paint::red(format!("-{}", deleted))
+            )?;
+        }
+        tw.flush()?;
     }
     Ok(())
 }
 
-fn list(repo: &Repository, range: Option<String>) -> anyhow::Result<()> {
-    walk_new(repo, range.as_ref(), |oid| println!("{}", oid))
-}
+fn mr_squash_preview(repo: &Repository, target: &str, show_patch: bool) -> anyhow::Result<()> {
+    let MRWithVersions { mr, versions } = cached_mr(repo, target)?;
+    let (version, info) = versions
+        .last_key_value()
+        .ok_or_else(|| anyhow!("!{} has no versions", mr.iid.0))?;
+    let (base, head) = resolve_version(repo, info)?;
+    // Diffing the trees directly skips over the intermediate commits
+    let diff = repo.diff_tree_to_tree(Some(&base.tree()?), Some(&head.tree()?), None)?;
 
-fn show(repo: &Repository, revspec: &str) -> anyhow::Result<()> {
-    let oid = repo.revparse_single(revspec)?.peel_to_commit()?.id();
-    let status = lookup(repo, oid)?;
-    println!("{} {} {:?}", revspec, oid, status);
+    pager::Pager::with_pager("less -FRSX").setup();
+    println!(
+        "{} {} {} (squashed)",
+        Paint::yellow(format!("merge_request {}", fmt_mr_ref(repo, &mr))),
+        version,
+        mr.title
+    );
+    println!();
+    print_diff_stat(&diff)?;
+    if show_patch {
+        println!();
+        print_patch(&diff)?;
+    }
     Ok(())
 }
 
-fn add_note(repo: &Repository, oid: Oid, verb: &str) -> anyhow::Result<()> {
-    let sig = repo.signature()?;
-    let new_note = format!(
-        "{}-by: {} <{}>",
-        verb,
-        sig.name().unwrap_or(""),
-        sig.email().unwrap_or(""),
-    );
-    append_note(repo, oid, &new_note)
+/// The given version of the MR (counting from 1, as the user does), or the
+/// latest one
+fn pick_version<'a>(
+    mr: &MergeRequest,
+    versions: &'a BTreeMap<Version, VersionInfo>,
+    version: Option<u16>,
+) -> anyhow::Result<(Version, &'a VersionInfo)> {
+    match version {
+        Some(x) => {
+            let v = x
+                .checked_sub(1)
+                .map(Version)
+                .ok_or_else(|| anyhow!("Versions are numbered from 1"))?;
+            let info = versions
+                .get(&v)
+                .ok_or_else(|| anyhow!("!{} has no version {}", mr.iid.0, v))?;
+            Ok((v, info))
+        }
+        None => versions
+            .last_key_value()
+            .map(|(v, info)| (*v, info))
+            .ok_or_else(|| anyhow!("!{} has no versions", mr.iid.0)),
+    }
 }
 
-pub struct GitlabConfig {
-    pub host: String,
-    pub project_id: ProjectId,
-    pub token: String,
+fn mr_checkout(repo: &Repository, target: &str, version: Option<u16>) -> anyhow::Result<()> {
+    let MRWithVersions { mr, versions } = cached_mr(repo, target)?;
+    let (version, info) = pick_version(&mr, &versions, version)?;
+    let commit = repo
+        .find_commit(info.head.as_oid())
+        .map_err(|e| anyhow!("{} (try running git fetch)", e))?;
+    let mut opts = git2::StatusOptions::new();
+    opts.include_untracked(false).include_ignored(false);
+    if !repo.statuses(Some(&mut opts))?.is_empty() {
+        return Err(anyhow!(
+            "You have uncommitted changes; commit or stash them first"
+        ));
+    }
+    repo.checkout_tree(
+        commit.as_object(),
+        Some(git2::build::CheckoutBuilder::new().safe()),
+    )?;
+    repo.set_head_detached(commit.id())?;
+    println!(
+        "HEAD is now at {} {} ({} of {})",
+        Paint::yellow(commit.as_object().short_id()?.as_str().unwrap_or("")),
+        commit.summary().unwrap_or(""),
+        version,
+        fmt_mr_ref(repo, &mr)
+    );
+    println!("Use \"git checkout -\" to go back");
+    Ok(())
 }
 
-impl GitlabConfig {
-    fn load(repo: &Repository) -> anyhow::Result<GitlabConfig> {
-        info!("Loading the config");
-        let config = repo.config()?;
-        Ok(GitlabConfig {
-            host: config
-                .get_string("gitlab.url")
-                .unwrap_or_else(|_| "gitlab.com".into()),
-            project_id: ProjectId(config.get_i64("gitlab.projectId")? as u64),
-            token: config.get_string("gitlab.privateToken")?,
-        })
+fn mr_patch(repo: &Repository, target: &str, output_dir: &Path) -> anyhow::Result<()> {
+    let MRWithVersions { mr, versions } = cached_mr(repo, target)?;
+    let (version, info) = pick_version(&mr, &versions, None)?;
+    let (base, head) =
+        resolve_version(repo, info).map_err(|e| anyhow!("{} (try running git fetch)", e))?;
+    let mut walk = repo.revwalk()?;
+    walk.push(head.id())?;
+    walk.hide(base.id())?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
+    let mut commits = vec![];
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        if commit.parent_count() <= 1 {
+            commits.push(commit);
+        }
+    }
+    if commits.is_empty() {
+        println!("{} has no commits to export", fmt_mr_ref(repo, &mr));
+        return Ok(());
     }
-}
 
-fn db_path(repo: &Repository) -> PathBuf {
-    OPTS.db.clone().unwrap_or_else(|| repo.path().join("orpa"))
+    std::fs::create_dir_all(output_dir)?;
+    let n = commits.len();
+    for (i, commit) in commits.iter().enumerate() {
+        let summary = commit.summary().unwrap_or("");
+        let email = git2::Email::from_diff(
+            &commit_diff(repo, commit)?,
+            i + 1,
+            n,
+            &commit.id(),
+            summary,
+            commit.body().unwrap_or(""),
+            &commit.author(),
+            &mut git2::EmailCreateOptions::new(),
+        )?;
+        let path = output_dir.join(format!("{:04}-{}.patch", i + 1, patch_slug(summary)));
+        std::fs::write(&path, email.as_slice())?;
+        println!("{}", path.display());
+    }
+    println!(
+        "Wrote {} patches for {} ({})",
+        n,
+        fmt_mr_ref(repo, &mr),
+        version
+    );
+    Ok(())
 }
 
-fn cached_mrs(repo: &Repository) -> anyhow::Result<Vec<MRWithVersions>> {
-    let mr_dir = db_path(repo).join("merge_requests");
-    let mut mrs = vec![];
-    for entry in std::fs::read_dir(mr_dir)? {
-        let mr: MRWithVersions = serde_json::from_reader(File::open(entry?.path())?)?;
-        mrs.push(mr);
+/// The commit summary, made safe for a filename the way `git format-patch`
+/// does it: runs of anything other than letters, digits, '.' and '_' become
+/// a single '-'
+fn patch_slug(summary: &str) -> String {
+    const MAX_LEN: usize = 52;
+    let mut ret = String::new();
+    for c in summary.chars() {
+        if c.is_ascii_alphanumeric() || c == '.' || c == '_' {
+            ret.push(c);
+        } else if !ret.is_empty() && !ret.ends_with('-') {
+            ret.push('-');
+        }
     }
-    mrs.sort_by_key(|mr| std::cmp::Reverse(mr.mr.updated_at));
-    Ok(mrs)
+    ret.truncate(MAX_LEN);
+    ret.trim_end_matches(['-', '.']).to_owned()
 }
 
-fn merge_request(repo: &Repository, target: String) -> anyhow::Result<()> {
-    pager::Pager::with_pager("less -FRSX").setup();
-    let target = target.trim_matches(|c: char| !c.is_numeric());
-    let path = db_path(repo).join("merge_requests").join(target);
-    let MRWithVersions { mr, versions } = serde_json::from_reader(File::open(path)?)?;
+fn mr_diff(repo: &Repository, target: &str, version: Option<u16>) -> anyhow::Result<()> {
+    let MRWithVersions { mr, versions } = cached_mr(repo, target)?;
+    let (version, info) = pick_version(&mr, &versions, version)?;
+    let (base, head) = resolve_version(repo, info)?;
+    let mut walk = repo.revwalk()?;
+    walk.push(head.id())?;
+    walk.hide(base.id())?;
+    walk.set_sorting(git2::Sort::TOPOLOGICAL | git2::Sort::REVERSE)?;
 
-    let config = repo.config()?;
-    let me = config.get_string("gitlab.username")?;
-    print_mr(&me, &mr);
-    println!();
-    for (&version, info) in &versions {
-        print_version(repo, version, info)?;
+    pager::Pager::with_pager("less -FRSX").setup();
+    println!(
+        "{} {} {}",
+        Paint::yellow(format!("merge_request {}", fmt_mr_ref(repo, &mr))),
+        version,
+        mr.title
+    );
+    for oid in walk {
+        let commit = repo.find_commit(oid?)?;
+        let diff = commit_diff(repo, &commit)?;
+        println!();
+        print_commit(commit);
+        println!();
+        print_patch(&diff)?;
     }
-    println!();
-    if let Some((_, version)) = versions.last_key_value() {
-        if let Ok((base, head)) = resolve_version(repo, version) {
-            let diff = repo.diff_tree_to_tree(Some(&base.tree()?), Some(&head.tree()?), None)?;
-            print_diff_stat(diff)?;
-            println!();
-        }
+    Ok(())
+}
 
-        let range = format!("{}..{}", &version.base.0, &version.head.0);
-        let mut walk = repo.revwalk()?;
-        walk.push_range(&range)?;
-        walk.set_sorting(git2::Sort::REVERSE)?;
-        for oid in walk {
-            let commit = repo.find_commit(oid?)?;
-            print_commit(commit);
+/// Print a diff in "git diff" format, with colours
+fn print_patch(diff: &git2::Diff) -> anyhow::Result<()> {
+    diff.print(git2::DiffFormat::Patch, |_, _, line| {
+        let content = String::from_utf8_lossy(line.content());
+        match line.origin() {
+            '+' => print!("{}", Paint::green(format!("+{}", content))),
+            '-' => print!("{}", Paint::red(format!("-{}", content))),
+            ' ' => print!(" {}", content),
+            'F' => print!("{}", Paint::new(content).bold()),
+            'H' => print!("{}", Paint::cyan(content)),
+            _ => print!("{}", content),
         }
-    }
+        true
+    })?;
     Ok(())
 }
 
@@ -504,14 +3532,64 @@ fn git_time_to_chrono(time: git2::Time) -> chrono::DateTime<chrono::FixedOffset>
     date.with_timezone(&tz)
 }
 
-fn merge_requests(repo: &Repository, include_all: bool) -> anyhow::Result<()> {
+/// A date (taken as midnight UTC) or an RFC 3339 timestamp
+fn parse_since(x: &str) -> anyhow::Result<chrono::DateTime<chrono::Utc>> {
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(x, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).unwrap().and_utc());
+    }
+    chrono::DateTime::parse_from_rfc3339(x)
+        .map(|x| x.with_timezone(&chrono::Utc))
+        .map_err(|_| {
+            anyhow!(
+                "Bad date \"{}\" (expected YYYY-MM-DD or an RFC 3339 timestamp)",
+                x
+            )
+        })
+}
+
+fn merge_requests(
+    repo: &Repository,
+    include_all: bool,
+    sort: MrsSort,
+    label: Option<String>,
+    author: Option<String>,
+    target_branch: Option<String>,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+) -> anyhow::Result<()> {
     pager::Pager::with_pager("less -FRSX").setup();
     let config = repo.config()?;
-    let me = config.get_string("gitlab.username")?;
+    let me = config::get_string(&config, "gitlab.username")?;
     let mut mrs = cached_mrs(repo)?;
-    mrs.retain(|mr| include_all || (!mr.mr.draft && mr.mr.author.username != me));
+    mrs.retain(|mr| {
+        include_all
+            || (!mr.mr.draft
+                && mr.mr.author.username != me
+                && matches!(
+                    mr.mr.state,
+                    MergeRequestState::Opened | MergeRequestState::Reopened
+                ))
+    });
+    if let Some(label) = label {
+        mrs.retain(|mr| mr.mr.labels.iter().any(|x| x.eq_ignore_ascii_case(&label)));
+    }
+    if let Some(author) = author {
+        let is_username = |mr: &MRWithVersions| mr.mr.author.username.eq_ignore_ascii_case(&author);
+        if mrs.iter().any(is_username) {
+            mrs.retain(is_username);
+        } else {
+            let author = author.to_lowercase();
+            mrs.retain(|mr| mr.mr.author.name.to_lowercase().contains(&author));
+        }
+    }
+    if let Some(target) = target_branch {
+        mrs.retain(|mr| mr.mr.target_branch == target);
+    }
+    if let Some(since) = since {
+        mrs.retain(|mr| mr.mr.updated_at > since);
+    }
+    sort_mrs(repo, &mut mrs, sort);
     for MRWithVersions { mr, versions } in mrs {
-        print_mr(&me, &mr);
+        print_mr(repo, &me, &mr);
         println!();
         for (&version, info) in &versions {
             print_version(repo, version, info)?;
@@ -522,13 +3600,410 @@ fn merge_requests(repo: &Repository, include_all: bool) -> anyhow::Result<()> {
             .and_then(|(_, v)| resolve_version(repo, v).ok())
         {
             let diff = repo.diff_tree_to_tree(Some(&base.tree()?), Some(&head.tree()?), None)?;
-            print_diff_stat(diff)?;
+            print_diff_stat(&diff)?;
         }
         println!();
     }
     Ok(())
 }
 
+fn search_notes(repo: &Repository, pattern: &str, case_insensitive: bool) -> anyhow::Result<()> {
+    let re = regex::RegexBuilder::new(pattern)
+        .case_insensitive(case_insensitive)
+        .build()?;
+    let note_times = note_times(repo)?;
+    let mut hits = vec![];
+    for (_, oid) in all_notes(repo)? {
+        let Some(note) = get_note(repo, oid)? else {
+            continue;
+        };
+        if note.lines().any(|line| re.is_match(line)) {
+            hits.push((note_times.get(&oid).map(|x| x.seconds()), oid));
+        }
+    }
+    hits.sort();
+    for (_, oid) in hits {
+        match repo.find_commit(oid) {
+            Ok(c) => println!("{} {}", Paint::yellow(oid), c.summary().unwrap_or("")),
+            Err(_) => println!("{} {}", Paint::yellow(oid), Paint::red("(missing)")),
+        }
+    }
+    Ok(())
+}
+
+/// Print the noted commits, grouped by the people named in the notes.  The
+/// most recently active reviewers come first.
+fn recent_by_reviewer(repo: &Repository) -> anyhow::Result<()> {
+    let note_times = note_times(repo)?;
+    let mut by_reviewer = HashMap::<String, Vec<(i64, Oid)>>::new();
+    for oid in review_db::recent_notes(repo)? {
+        let Some(note) = get_note(repo, oid)? else {
+            continue;
+        };
+        let when = note_times.get(&oid).map_or(0, |x| x.seconds());
+        for trailer in parse_trailers(&note) {
+            let oids = by_reviewer.entry(trailer.name).or_default();
+            // Someone might have both authored and reviewed a commit
+            if !oids.iter().any(|x| x.1 == oid) {
+                oids.push((when, oid));
+            }
+        }
+    }
+    let mut by_reviewer = by_reviewer.into_iter().collect::<Vec<_>>();
+    for (_, oids) in &mut by_reviewer {
+        oids.sort_by_key(|x| std::cmp::Reverse(x.0));
+    }
+    by_reviewer.sort_by_key(|(_, oids)| std::cmp::Reverse(oids[0].0));
+    for (i, (name, oids)) in by_reviewer.iter().enumerate() {
+        if i > 0 {
+            println!();
+        }
+        let plural = if oids.len() == 1 { "" } else { "s" };
+        println!("{} ({} review{})", Paint::green(name), oids.len(), plural);
+        for (_, oid) in oids {
+            let c = repo.find_commit(*oid)?;
+            let short_id = c.as_object().short_id()?;
+            println!(
+                "  {} {}",
+                Paint::yellow(short_id.as_str().unwrap_or("")),
+                c.summary().unwrap_or("")
+            );
+        }
+    }
+    Ok(())
+}
+
+fn orphans(repo: &Repository, clean: bool) -> anyhow::Result<()> {
+    let orphans = review_db::orphaned_notes(repo)?;
+    for &oid in &orphans {
+        let note = get_note(repo, oid)?.unwrap_or_default();
+        println!("{} {}", Paint::yellow(oid), note.lines().join(", "));
+    }
+    if clean {
+        delete_notes(repo, &orphans)?;
+    }
+    Ok(())
+}
+
+fn gc_notes(repo: &Repository, fix: bool) -> anyhow::Result<()> {
+    const N_SAMPLES: usize = 10;
+    let orphans = review_db::orphaned_notes(repo)?;
+    if orphans.is_empty() {
+        println!("No orphaned notes");
+        return Ok(());
+    }
+    if !fix {
+        println!("Found {} orphaned notes, eg.:", orphans.len());
+        for oid in orphans.iter().take(N_SAMPLES) {
+            println!("  {}", Paint::yellow(oid));
+        }
+        println!("Run with --fix to delete them");
+        return Ok(());
+    }
+    delete_notes(repo, &orphans)?;
+    println!("Deleted {} orphaned notes", orphans.len());
+    Ok(())
+}
+
+/// Delete the notes attached to the given commits
+fn delete_notes(repo: &Repository, oids: &[Oid]) -> anyhow::Result<()> {
+    let sig = repo.signature()?;
+    for &oid in oids {
+        repo.note_delete(oid, notes_ref(), &sig, &sig)?;
+    }
+    invalidate_reviews(repo);
+    Ok(())
+}
+
+/// Sort MRs in place.  Ties are broken by IID.
+fn sort_mrs(repo: &Repository, mrs: &mut [MRWithVersions], sort: MrsSort) {
+    use std::cmp::Reverse;
+    mrs.sort_by_key(|x| x.mr.iid);
+    match sort {
+        MrsSort::UpdatedAt => mrs.sort_by_key(|x| Reverse(x.mr.updated_at)),
+        MrsSort::CreatedAt => mrs.sort_by_key(|x| Reverse(x.mr.created_at)),
+        MrsSort::Title => mrs.sort_by(|x, y| x.mr.title.cmp(&y.mr.title)),
+        MrsSort::Author => mrs.sort_by(|x, y| x.mr.author.username.cmp(&y.mr.author.username)),
+        MrsSort::UnreviewedCount => {
+            mrs.sort_by_cached_key(|x| Reverse(mr_unreviewed_count(repo, &x.versions).unwrap_or(0)))
+        }
+    }
+}
+
+/// The number of unreviewed commits in the MR's latest version
+fn mr_unreviewed_count(
+    repo: &Repository,
+    versions: &BTreeMap<Version, VersionInfo>,
+) -> anyhow::Result<usize> {
+    let (_, latest) = versions
+        .last_key_value()
+        .ok_or_else(|| anyhow!("Can't find any versions"))?;
+    Ok(version_stats(repo, latest)?[Status::New])
+}
+
+fn transfer_note(repo: &Repository, from: &str, to: &str, force: bool) -> anyhow::Result<()> {
+    let from = repo.revparse_single(from)?.peel_to_commit()?;
+    let to = repo.revparse_single(to)?.peel_to_commit()?;
+    let note =
+        get_note(repo, from.id())?.ok_or_else(|| anyhow!("{} doesn't have a note", from.id()))?;
+    let score = similiar_commits(repo, &to)?
+        .into_iter()
+        .find(|(oid, _)| *oid == from.id())
+        .map_or(0., |(_, x)| x.score());
+    println!("Similarity: {:.02}%", score * 100.);
+    let identical = commit_diff_digest(repo, &from)? == commit_diff_digest(repo, &to)?;
+    if !identical && !force {
+        return Err(anyhow!(
+            "The diffs of {} and {} differ; use --force to copy the note anyway",
+            from.id(),
+            to.id(),
+        ));
+    }
+    // Line by line, so that lines which `to` already has aren't repeated
+    let mut summary = String::new();
+    for line in note.lines() {
+        summary = merge_note(repo, to.id(), line)?;
+    }
+    println!("{}: {}", to.id(), summary);
+    Ok(())
+}
+
+fn copy_note(repo: &Repository, from: &str, to: &str, force: bool) -> anyhow::Result<()> {
+    let from = repo.revparse_single(from)?.peel_to_commit()?.id();
+    let to = repo.revparse_single(to)?.peel_to_commit()?.id();
+    let note = get_note(repo, from)?.ok_or_else(|| anyhow!("{} doesn't have a note", from))?;
+    if !force && get_note(repo, to)?.is_some() {
+        return Err(anyhow!(
+            "{} already has a note; use --force to add to it anyway",
+            to
+        ));
+    }
+    println!("Copying the note from {}:", from);
+    let mut summary = String::new();
+    for line in note.lines() {
+        println!("    {}", line);
+        summary = merge_note(repo, to, line)?;
+    }
+    println!("{}: {}", to, summary);
+    Ok(())
+}
+
+fn rewrite_notes(repo: &Repository, map_file: Option<PathBuf>) -> anyhow::Result<()> {
+    let map = match map_file {
+        Some(path) => {
+            std::fs::read_to_string(&path).map_err(|e| anyhow!("{}: {}", path.display(), e))?
+        }
+        None => std::fs::read_to_string(repo.path().join("rebase-merge/rewritten-list"))
+            .map_err(|_| anyhow!("No rebase in progress; use --map-file"))?,
+    };
+    let sig = repo.signature()?;
+    let mut n_moved = 0;
+    for line in map.lines() {
+        // The post-rewrite hook may give a third column, which we ignore
+        let mut words = line.split_whitespace();
+        let (Some(old), Some(new)) = (words.next(), words.next()) else {
+            continue;
+        };
+        let old = Oid::from_str(old)?;
+        let new = Oid::from_str(new)?;
+        let Some(note) = get_note(repo, old)? else {
+            continue;
+        };
+        let mut summary = String::new();
+        for line in note.lines() {
+            summary = merge_note(repo, new, line)?;
+        }
+        repo.note_delete(old, notes_ref(), &sig, &sig)?;
+        println!("{} -> {}: {}", old, new, summary);
+        n_moved += 1;
+    }
+    invalidate_reviews(repo);
+    println!("Moved {} notes", n_moved);
+    Ok(())
+}
+
+fn stale(repo: &Repository, days: u64, include_own: bool) -> anyhow::Result<()> {
+    let me = config::get_string(&repo.config()?, "gitlab.username")?;
+    let now = chrono::Utc::now();
+    let mut mrs = cached_open_mrs(repo)?;
+    mrs.retain(|x| {
+        now - x.mr.updated_at > chrono::Duration::days(days as i64)
+            && (include_own || x.mr.author.username != me)
+    });
+    // Longest-forgotten first
+    mrs.reverse();
+
+    let mut tw = TabWriter::new(std::io::stdout()).ansi(true);
+    for MRWithVersions { mr, versions } in &mrs {
+        let progress = match versions.last_key_value() {
+            Some((_, latest)) => match count_reviewed(repo, latest) {
+                Ok((n_unreviewed, n_total)) => {
+                    format!("{}/{} reviewed", n_total - n_unreviewed, n_total)
+                }
+                Err(_) => "commits missing".into(),
+            },
+            None => "no versions".into(),
+        };
+        writeln!(
+            tw,
+            "{}\t{} days\t{}\t{}\t({})",
+            Paint::yellow(fmt_mr_ref(repo, mr)),
+            Paint::blue((now - mr.updated_at).num_days()),
+            Paint::green(&mr.author.username),
+            &mr.title,
+            progress,
+        )?;
+    }
+    tw.flush()?;
+    Ok(())
+}
+
+/// Added to every comment posted by `orpa nag`, so we can tell which MRs
+/// have already been nagged
+const NAG_MARKER: &str = "<!-- orpa-nag -->";
+
+/// The comment posted by `nag` (unless there's a template) and `remind`.
+/// `when` is how long ago the MR was last updated, eg. "3 days ago".
+fn reminder_body(when: &str, n_unreviewed: usize) -> String {
+    format!(
+        "This MR was last updated {} and still has {} unreviewed commit(s).  \
+         Could someone take a look?",
+        when, n_unreviewed,
+    )
+}
+
+fn nag(repo: &Repository, target: Option<&str>, days: u64, dry_run: bool) -> anyhow::Result<()> {
+    let mrs = match target {
+        Some(x) => vec![cached_mr(repo, x)?],
+        None => cached_open_mrs(repo)?,
+    };
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(days as i64);
+    let template = repo.config()?.get_string("orpa.nagTemplate").ok();
+    for MRWithVersions { mr, versions } in mrs {
+        let mr_ref = fmt_mr_ref(repo, &mr);
+        if mr.updated_at > cutoff {
+            info!("{}: Updated recently", mr_ref);
+            continue;
+        }
+        let Some((_, latest)) = versions.last_key_value() else {
+            continue;
+        };
+        let n_unreviewed = version_stats(repo, latest)?[Status::New];
+        if n_unreviewed == 0 {
+            info!("{}: Nothing to review", mr_ref);
+            continue;
+        }
+        // Offline, we can't tell whether the MR has been nagged already
+        let comments = if OPTS.offline {
+            vec![]
+        } else {
+            let config = GitlabConfig::for_project(repo, mr.project_id)?;
+            match fetch::query_comments(&config, mr.iid) {
+                Ok(x) => x,
+                Err(e) => {
+                    error!("{}: {}", mr_ref, e);
+                    continue;
+                }
+            }
+        };
+        if comments
+            .iter()
+            .any(|x| x.body.contains(NAG_MARKER) && x.created_at > cutoff)
+        {
+            info!("{}: Already nagged", mr_ref);
+            continue;
+        }
+        let when = timeago::Formatter::new().convert_chrono(mr.updated_at, chrono::Utc::now());
+        let body = match &template {
+            Some(template) => {
+                let tokens = ["author", "unreviewed", "updated"];
+                render_template("orpa.nagTemplate", template, &tokens, |token| match token {
+                    "author" => Some(format!("@{}", mr.author.username)),
+                    "unreviewed" => Some(n_unreviewed.to_string()),
+                    "updated" => Some(when.clone()),
+                    _ => None,
+                })?
+            }
+            None => reminder_body(&when, n_unreviewed),
+        };
+        let body = format!("{}\n\n{}", body, NAG_MARKER);
+        if dry_run || OPTS.offline {
+            let cached = if OPTS.offline { " [cached data]" } else { "" };
+            println!("Would post on {}{}:", mr_ref, cached);
+            for line in body.lines() {
+                println!("    {}", line);
+            }
+            continue;
+        }
+        let config = GitlabConfig::for_project(repo, mr.project_id)?;
+        let gl = fetch::connect(&config)?;
+        match fetch::post_comment(&gl, &config, mr.iid, &body) {
+            Ok(()) => println!("Posted a reminder on {}", mr_ref),
+            Err(e) => error!("{}: Couldn't post a reminder: {}", mr_ref, e),
+        }
+    }
+    Ok(())
+}
+
+fn remind(repo: &Repository, days: u64, post_gitlab: bool) -> anyhow::Result<()> {
+    let cutoff = chrono::Utc::now() - chrono::Duration::days(days as i64);
+    let mut stale = vec![];
+    for MRWithVersions { mr, versions } in cached_mrs(repo)? {
+        if !matches!(
+            mr.state,
+            MergeRequestState::Opened | MergeRequestState::Reopened
+        ) || mr.updated_at > cutoff
+        {
+            continue;
+        }
+        if versions.is_empty() {
+            continue;
+        }
+        let n_unreviewed = match mr_unreviewed_count(repo, &versions) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("{}: {}", fmt_mr_ref(repo, &mr), e);
+                continue;
+            }
+        };
+        if n_unreviewed > 0 {
+            stale.push((mr, n_unreviewed));
+        }
+    }
+
+    let mut tw = TabWriter::new(std::io::stdout()).ansi(true);
+    for (mr, n_unreviewed) in &stale {
+        let when = timeago::Formatter::new().convert_chrono(mr.updated_at, chrono::Utc::now());
+        writeln!(
+            tw,
+            "{}\t{}\t{}\t{}\t({} left to review)",
+            Paint::yellow(fmt_mr_ref(repo, mr)),
+            Paint::blue(&when),
+            Paint::green(&mr.author.username),
+            &mr.title,
+            n_unreviewed,
+        )?;
+    }
+    tw.flush()?;
+
+    if post_gitlab && OPTS.offline {
+        println!("Offline mode: not posting any reminders");
+    } else if post_gitlab {
+        for (mr, n_unreviewed) in &stale {
+            let config = GitlabConfig::for_project(repo, mr.project_id)?;
+            let gl = fetch::connect(&config)?;
+            let when = timeago::Formatter::new().convert_chrono(mr.updated_at, chrono::Utc::now());
+            let body = reminder_body(&when, *n_unreviewed);
+            let mr_ref = fmt_mr_ref(repo, mr);
+            match fetch::post_comment(&gl, &config, mr.iid, &body) {
+                Ok(()) => println!("Posted a reminder on {}", mr_ref),
+                Err(e) => error!("{}: Couldn't post a reminder: {}", mr_ref, e),
+            }
+        }
+    }
+    Ok(())
+}
+
 fn similar(repo: &Repository, revspec: &str) -> anyhow::Result<()> {
     let commit = repo.revparse_single(revspec)?.peel_to_commit()?;
     for (oid, x) in similiar_commits(repo, &commit)?.into_iter().take(10) {
@@ -572,6 +4047,9 @@ fn print_version(repo: &Repository, version: Version, info: &VersionInfo) -> any
             Paint::magenta(head.as_str().unwrap_or("")),
         );
     }
+    if let Some(created_at) = info.created_at {
+        print!(" {}", created_at.format("%Y-%m-%d %H:%M"));
+    }
 
     let (n_unreviewed, n_total) = count_reviewed(repo, info)?;
     if n_unreviewed != 0 {
@@ -586,7 +4064,7 @@ fn print_version(repo: &Repository, version: Version, info: &VersionInfo) -> any
     Ok(())
 }
 
-fn print_diff_stat(diff: git2::Diff) -> anyhow::Result<()> {
+fn print_diff_stat(diff: &git2::Diff) -> anyhow::Result<()> {
     let stats = diff.stats()?.to_buf(git2::DiffStatsFormat::FULL, 100)?;
     for l in stats.as_str().unwrap().lines() {
         match l.split_once('|') {
@@ -624,19 +4102,30 @@ pub fn fmt_state(x: MergeRequestState) -> &'static str {
     }
 }
 
-fn print_mr(me: &str, mr: &MergeRequest) {
+fn print_mr(repo: &Repository, me: &str, mr: &MergeRequest) {
     println!(
-        "{}{} ({} -> {})",
-        Paint::yellow("merge_request !"),
-        Paint::yellow(mr.iid.0),
+        "{} ({} -> {})",
+        Paint::yellow(format!("merge_request {}", fmt_mr_ref(repo, mr))),
         mr.source_branch,
         mr.target_branch,
     );
-    println!("Status: {}", fmt_state(mr.state));
+    match &mr.pipeline {
+        Some(x) => println!(
+            "Status: {} (pipeline {}){}",
+            fmt_state(mr.state),
+            fmt_pipeline_status(&x.status),
+            fmt_claim(mr),
+        ),
+        None => println!("Status: {}{}", fmt_state(mr.state), fmt_claim(mr)),
+    }
     println!("Author: {} (@{})", &mr.author.name, &mr.author.username);
     println!("Date:   {}", &mr.updated_at);
     println!();
     println!("    {}", &mr.title);
+    if !mr.labels.is_empty() {
+        println!();
+        println!("    {}", mr.labels.iter().map(|x| fmt_label(x)).join(" "));
+    }
 
     if let Some(desc) = mr.description.as_ref().filter(|x| !x.is_empty()) {
         println!();
@@ -658,6 +4147,34 @@ fn print_mr(me: &str, mr: &MergeRequest) {
     }
 }
 
+fn fmt_pipeline_status(status: &str) -> Paint<&'static str> {
+    match status {
+        "success" => Paint::green("✓"),
+        "failed" | "canceled" => Paint::red("✗"),
+        "created" | "waiting_for_resource" | "preparing" | "pending" | "running" | "scheduled" => {
+            Paint::yellow("⟳")
+        }
+        _ => Paint::new("?"),
+    }
+}
+
+/// Labels get a colour based on their name, so the same label always looks
+/// the same
+fn fmt_label(label: &str) -> Paint<&str> {
+    const COLORS: [yansi::Color; 6] = [
+        yansi::Color::Red,
+        yansi::Color::Green,
+        yansi::Color::Yellow,
+        yansi::Color::Blue,
+        yansi::Color::Magenta,
+        yansi::Color::Cyan,
+    ];
+    let hash = label
+        .bytes()
+        .fold(0u32, |h, b| h.wrapping_mul(31).wrapping_add(b as u32));
+    Paint::new(label).fg(COLORS[hash as usize % COLORS.len()])
+}
+
 /// Paths changed by an MR
 fn mr_paths(repo: &Repository, mr: &VersionInfo) -> anyhow::Result<Vec<PathBuf>> {
     let base = repo.find_commit(mr.base.as_oid())?.tree()?;
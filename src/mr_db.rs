@@ -1,6 +1,9 @@
 use crate::fetch::{MergeRequest, ObjectId};
+use anyhow::anyhow;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use std::collections::BTreeMap;
+use std::convert::TryFrom;
 use std::fmt;
 
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -13,9 +16,12 @@ pub struct MRWithVersions {
 
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub struct VersionInfo {
-    // TODO: pub time: DateTime,
     pub base: ObjectId,
     pub head: ObjectId,
+    /// When we first saw this version.  Versions recorded by older versions
+    /// of orpa don't have this.
+    #[serde(default)]
+    pub created_at: Option<DateTime<Utc>>,
 }
 
 impl fmt::Display for VersionInfo {
@@ -25,10 +31,60 @@ impl fmt::Display for VersionInfo {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize, PartialOrd, Ord)]
-pub struct Version(pub u8);
+pub struct Version(pub u16);
+
+impl Version {
+    /// The version which comes `n` versions after this one
+    pub fn plus(self, n: usize) -> anyhow::Result<Version> {
+        u16::try_from(n)
+            .ok()
+            .and_then(|n| self.0.checked_add(n))
+            .map(Version)
+            .ok_or_else(|| {
+                anyhow!(
+                    "Too many versions: orpa can only track {} versions per MR",
+                    u16::MAX as u32 + 1
+                )
+            })
+    }
+}
 
 impl fmt::Display for Version {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "v{}", self.0 + 1)
+        write!(f, "v{}", self.0 as u32 + 1)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn more_than_256_versions() {
+        let mut versions = BTreeMap::<Version, VersionInfo>::new();
+        for i in 0..=256 {
+            let version = match versions.last_key_value() {
+                Some((x, _)) => x.plus(1).unwrap(),
+                None => Version(0),
+            };
+            let info = VersionInfo {
+                base: ObjectId("base".into()),
+                head: ObjectId(format!("head{}", i)),
+                created_at: None,
+            };
+            versions.insert(version, info);
+        }
+        assert_eq!(versions.len(), 257);
+        assert_eq!(versions.last_key_value().unwrap().0, &Version(256));
+        // Survives a round-trip through the MR cache
+        let json = serde_json::to_string(&versions).unwrap();
+        let versions2: BTreeMap<Version, VersionInfo> = serde_json::from_str(&json).unwrap();
+        assert_eq!(versions, versions2);
+    }
+
+    #[test]
+    fn too_many_versions() {
+        assert!(Version(u16::MAX).plus(1).is_err());
+        assert!(Version(0).plus(usize::MAX).is_err());
     }
 }
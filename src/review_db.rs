@@ -1,20 +1,31 @@
+use crate::config::file_config;
 use crate::mr_db::VersionInfo;
-use crate::{get_idx, OPTS};
+use crate::{db_path, get_idx, OPTS};
 use anyhow::anyhow;
 use chrono::{DateTime, NaiveDateTime};
 use enum_map::{Enum, EnumMap};
-use git2::{Commit, Diff, DiffStatsFormat, ErrorCode, Oid, Repository, Time, Tree};
+use git2::{
+    Commit, Diff, DiffStatsFormat, ErrorCode, ObjectType, Oid, Repository, Signature, Time, Tree,
+};
 use itertools::Itertools;
 use sha1::{Digest, Sha1};
 use std::collections::{HashMap, HashSet};
 use std::convert::TryInto;
 use std::io::Write;
-use std::path::Path;
-use std::sync::{LazyLock, OnceLock};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, LazyLock, Mutex, OnceLock};
 use tracing::*;
 use yansi::Paint;
 
 pub fn append_note(repo: &Repository, oid: Oid, new_note: &str) -> anyhow::Result<()> {
+    let notes = merge_note(repo, oid, new_note)?;
+    println!("{}: {}", oid, notes);
+    Ok(())
+}
+
+/// Add a line to the commit's note (if it's not there already).  Returns a
+/// summary of the combined note.
+pub fn merge_note(repo: &Repository, oid: Oid, new_note: &str) -> anyhow::Result<String> {
     let sig = repo.signature()?;
     let old_note = get_note(repo, oid)?;
     let mut notes = HashSet::new();
@@ -27,13 +38,17 @@ pub fn append_note(repo: &Repository, oid: Oid, new_note: &str) -> anyhow::Resul
     let combined_note = notes.iter().join("\n");
     let notes_ref = notes_ref();
     repo.note(&sig, &sig, notes_ref, oid, &combined_note, true)?;
-    println!("{}: {}", oid, notes.iter().join(", "));
-    Ok(())
+    invalidate_reviews(repo);
+    Ok(notes.iter().join(", "))
 }
 
-fn notes_ref() -> Option<&'static str> {
-    static NOTES_REF: LazyLock<Option<String>> =
-        LazyLock::new(|| OPTS.notes_ref.as_ref().map(|x| format!("refs/notes/{}", x)));
+pub fn notes_ref() -> Option<&'static str> {
+    static NOTES_REF: LazyLock<Option<String>> = LazyLock::new(|| {
+        OPTS.notes_ref
+            .as_ref()
+            .or(file_config().orpa.notes_ref.as_ref())
+            .map(|x| format!("refs/notes/{}", x))
+    });
     NOTES_REF.as_ref().map(|x| x.as_str())
 }
 
@@ -46,24 +61,121 @@ pub fn get_note(repo: &Repository, oid: Oid) -> anyhow::Result<Option<String>> {
     }
 }
 
-/// Actually returns all notes...
-pub fn recent_notes(repo: &Repository) -> anyhow::Result<Vec<Oid>> {
+/// A line of a note of the form "Reviewed-by: Joe Smith <joe@smith.net>".
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct Trailer {
+    /// The part before "-by", eg. "Reviewed"
+    pub verb: String,
+    pub name: String,
+    pub email: String,
+}
+
+impl Trailer {
+    pub fn parse(line: &str) -> Option<Trailer> {
+        let (key, value) = line.split_once(':')?;
+        let verb = key.trim().strip_suffix("-by")?;
+        let value = value.trim();
+        let (name, email) = match value.split_once('<') {
+            Some((name, email)) => (name.trim(), email.trim_end_matches('>').trim()),
+            None => (value, ""),
+        };
+        Some(Trailer {
+            verb: verb.to_owned(),
+            name: name.to_owned(),
+            email: email.to_owned(),
+        })
+    }
+}
+
+/// All the trailers in a note.  Lines which aren't trailers are ignored.
+pub fn parse_trailers(note: &str) -> Vec<Trailer> {
+    note.lines().filter_map(Trailer::parse).collect()
+}
+
+/// When each note was last modified.
+///
+/// libgit2 only gives us the signature of the notes ref's tip, so we have
+/// to walk its history to find the commit which last touched each note.
+pub fn note_times(repo: &Repository) -> anyhow::Result<HashMap<Oid, Time>> {
+    Ok(note_authors(repo)?
+        .into_iter()
+        .map(|(oid, sig)| (oid, sig.when()))
+        .collect())
+}
+
+/// Who last modified each note, and when
+pub fn note_authors(repo: &Repository) -> anyhow::Result<HashMap<Oid, Signature<'static>>> {
     let notes_ref = notes_ref().unwrap_or("refs/notes/commits");
-    let notes = match repo.find_reference(notes_ref) {
+    let mut ret = HashMap::new();
+    let tip = match repo.refname_to_id(notes_ref) {
         Ok(x) => x,
-        Err(_) => return Ok(vec![]),
+        Err(_) => return Ok(ret),
     };
-    let tree = notes.peel_to_commit()?.tree()?;
-    let mut ret = Vec::with_capacity(tree.len());
-    for x in tree.iter() {
-        let name = x
-            .name()
-            .ok_or_else(|| anyhow!("Commit is not even unicode, let alone hex!"))?;
-        ret.push(Oid::from_str(name)?);
+    let mut walk = repo.revwalk()?;
+    walk.push(tip)?;
+    for notes_commit in walk {
+        let notes_commit = repo.find_commit(notes_commit?)?;
+        let old_tree = match notes_commit.parent(0) {
+            Ok(parent) => Some(parent.tree()?),
+            Err(_) => None,
+        };
+        let diff = repo.diff_tree_to_tree(old_tree.as_ref(), Some(&notes_commit.tree()?), None)?;
+        for delta in diff.deltas() {
+            // Notes may be fanned-out into directories, eg. "ab/cdef..."
+            let path = delta.new_file().path().and_then(|x| x.to_str());
+            let Some(oid) = path.and_then(|x| Oid::from_str(&x.replace('/', "")).ok()) else {
+                continue;
+            };
+            // We're walking newest-first, so keep the first time we see it
+            ret.entry(oid)
+                .or_insert_with(|| notes_commit.author().to_owned());
+        }
     }
     Ok(ret)
 }
 
+/// Actually returns all notes...
+///
+/// Notes on commits which no longer exist (eg. because they were rebased
+/// away) are skipped.  See `orphaned_notes`.
+pub fn recent_notes(repo: &Repository) -> anyhow::Result<Vec<Oid>> {
+    let mut ret = all_noted_oids(repo)?;
+    ret.retain(|&oid| commit_exists(repo, oid));
+    Ok(ret)
+}
+
+/// Notes attached to commits which aren't in the repo any more
+pub fn orphaned_notes(repo: &Repository) -> anyhow::Result<Vec<Oid>> {
+    let mut ret = all_noted_oids(repo)?;
+    ret.retain(|&oid| !commit_exists(repo, oid));
+    Ok(ret)
+}
+
+fn commit_exists(repo: &Repository, oid: Oid) -> bool {
+    match repo.find_commit(oid) {
+        Ok(_) => true,
+        Err(e) if e.code() == ErrorCode::NotFound => false,
+        // Something else is wrong; let the caller find out later
+        Err(_) => true,
+    }
+}
+
+/// Every (note blob, annotated commit) pair.  If nobody has left a note yet,
+/// there's nothing to return.
+pub fn all_notes(repo: &Repository) -> anyhow::Result<Vec<(Oid, Oid)>> {
+    match repo.notes(notes_ref()) {
+        Ok(notes) => Ok(notes.collect::<Result<_, _>>()?),
+        Err(e) if e.code() == ErrorCode::NotFound => Ok(vec![]),
+        Err(e) => Err(e.into()),
+    }
+}
+
+fn all_noted_oids(repo: &Repository) -> anyhow::Result<Vec<Oid>> {
+    // Don't read the notes tree directly: once there are enough notes, git
+    // fans it out into subdirectories
+    Ok(all_notes(repo)?.into_iter().map(|(_, oid)| oid).collect())
+}
+
 /// Iterate over the lines in the commit's textual representation.
 ///
 /// Covers the commit message and diff, but no other metadata.
@@ -77,7 +189,8 @@ macro_rules! commit_lines {
                 &$commit.id(),
                 "",
                 "",
-                &git2::Signature::now("", "")?,
+                // Dropped below, but libgit2 won't accept an empty one
+                &git2::Signature::now("orpa", "orpa")?,
                 &mut git2::EmailCreateOptions::new(),
             )?
             .as_slice(),
@@ -105,6 +218,7 @@ impl Comparison {
 }
 
 /// For each reviewed commit, compute its similarity to the given commit.
+/// Commits which were only skipped or bookmarked don't count.
 ///
 /// Simliarity is defined as follows:
 ///
@@ -113,14 +227,30 @@ impl Comparison {
 /// Note that this means that a commit which is a superset will get a
 /// perfect score.
 pub fn similiar_commits(repo: &Repository, c: &Commit) -> anyhow::Result<Vec<(Oid, Comparison)>> {
-    let idx = get_idx(repo)?;
+    similar_in(repo, get_idx(repo)?, &reviewed_commits(repo), c)
+}
+
+fn similar_in(
+    repo: &Repository,
+    idx: &LineIdx,
+    reviews: &Reviews,
+    c: &Commit,
+) -> anyhow::Result<Vec<(Oid, Comparison)>> {
     let mut scores: HashMap<Oid, usize> = HashMap::new();
-    let all_lines: HashSet<Line> = commit_lines!(repo, c)
-        .map(|line| Line(Sha1::digest(line).into()))
-        .collect();
+    let mut all_lines = HashSet::new();
+    for line in commit_lines!(repo, c) {
+        let line = Line(Sha1::digest(line).into());
+        // Popular lines aren't in the index, so don't count them
+        if !idx.is_popular(line)? {
+            all_lines.insert(line);
+        }
+    }
     for &digest in &all_lines {
         for oid in idx.commits_containing(digest)? {
-            *(scores.entry(oid).or_default()) += 1;
+            // The index may be older than the notes
+            if reviews.is_indexable(&oid) {
+                *(scores.entry(oid).or_default()) += 1;
+            }
         }
     }
     let lines_in_left = all_lines.len();
@@ -144,11 +274,22 @@ pub fn similiar_commits(repo: &Repository, c: &Commit) -> anyhow::Result<Vec<(Oi
     Ok(scores)
 }
 
+/// Can't clash with the 20-byte digests
+const NOTES_TIP_KEY: &[u8] = b"notes_tip";
+
 pub struct LineIdx {
     /// What lines does this commit contain? (Oid => [Line])
     pub forward: sled::Tree,
     /// In what commits does this line appear? (Line => [Oid])
     pub reverse: sled::Tree,
+    /// Lines which were dropped by `compact()` for being too common
+    /// (Line => [])
+    pub popular: sled::Tree,
+    /// Whether a reviewed commit has exactly the same diff as the commit
+    /// with this diff digest (Line => [0 or 1]).  It's only valid for the
+    /// notes as they were at the commit stored under `NOTES_TIP_KEY`; use
+    /// `valid_dedup_cache` to read it.
+    pub dedup_cache: sled::Tree,
 }
 
 /// The SHA1 of a line in a commit's textual representation.
@@ -159,8 +300,10 @@ impl LineIdx {
     pub fn commits_containing(&self, line: Line) -> anyhow::Result<Vec<Oid>> {
         let bytes = self.reverse.get(line.0)?;
         let bytes = bytes.as_deref().unwrap_or(&[][..]);
+        // An interrupted refresh can leave duplicates behind
         bytes
             .chunks(20)
+            .unique()
             .map(|x| Oid::from_bytes(x).map_err(|e| e.into()))
             .collect()
     }
@@ -171,98 +314,810 @@ impl LineIdx {
         bytes.chunks(20).map(|x| Ok(Line(x.try_into()?))).collect()
     }
 
+    /// The dedup cache, emptied first if it was filled when the notes ref
+    /// pointed somewhere else.  Any change to the notes, whether it came
+    /// from orpa or not, can change the answers.
+    fn valid_dedup_cache(&self, notes_tip: Option<Oid>) -> anyhow::Result<&sled::Tree> {
+        let tip = notes_tip.map_or(vec![], |x| x.as_bytes().to_vec());
+        if self.dedup_cache.get(NOTES_TIP_KEY)?.as_deref() != Some(&tip[..]) {
+            info!("The notes have changed; clearing the dedup cache");
+            self.dedup_cache.clear()?;
+            self.dedup_cache.insert(NOTES_TIP_KEY, tip)?;
+        }
+        Ok(&self.dedup_cache)
+    }
+
+    /// Take a commit out of the forward and reverse trees
+    fn remove_commit(&self, oid: Oid) -> anyhow::Result<()> {
+        for line in self.lines_in(&oid)? {
+            let Some(oids) = self.reverse.get(line.0)? else {
+                continue;
+            };
+            let kept = oids
+                .chunks_exact(20)
+                .filter(|x| *x != oid.as_bytes())
+                .flatten()
+                .copied()
+                .collect::<Vec<u8>>();
+            if kept.is_empty() {
+                self.reverse.remove(line.0)?;
+            } else {
+                self.reverse.insert(line.0, kept)?;
+            }
+        }
+        self.forward.remove(oid.as_bytes())?;
+        Ok(())
+    }
+
     pub fn open(path: &Path) -> anyhow::Result<Self> {
-        let db = sled::open(path)?;
+        LineIdx::from_db(&sled::open(path)?)
+    }
+
+    fn from_db(db: &sled::Db) -> anyhow::Result<Self> {
         let forward = db.open_tree("forward")?;
         let reverse = db.open_tree("reverse")?;
+        let popular = db.open_tree("popular")?;
+        let dedup_cache = db.open_tree("dedup_cache")?;
         fn append(_: &[u8], existing: Option<&[u8]>, incoming: &[u8]) -> Option<Vec<u8>> {
             let mut ret = existing.unwrap_or_default().to_vec();
             ret.extend_from_slice(incoming);
             Some(ret)
         }
         reverse.set_merge_operator(append);
-        Ok(LineIdx { forward, reverse })
+        Ok(LineIdx {
+            forward,
+            reverse,
+            popular,
+            dedup_cache,
+        })
+    }
+
+    pub fn is_popular(&self, line: Line) -> anyhow::Result<bool> {
+        Ok(self.popular.contains_key(line.0)?)
+    }
+
+    /// Drop lines which appear in more than `threshold` (a fraction) of the
+    /// indexed commits, such as "" and "---".  They make the index bigger
+    /// without telling us much about which commits are similar.  Returns the
+    /// number of lines dropped.
+    pub fn compact(&self, threshold: f64) -> anyhow::Result<usize> {
+        // With only a handful of commits, lots of ordinary lines would look
+        // popular
+        const MIN_COMMITS: usize = 10;
+        let n_commits = self.forward.len();
+        if n_commits < MIN_COMMITS {
+            info!("Only {n_commits} commits in the index; not compacting");
+            return Ok(0);
+        }
+        let mut dropped = HashSet::new();
+        for x in self.reverse.iter() {
+            let (line, oids) = x?;
+            let n = oids.chunks(20).unique().count();
+            if n as f64 / n_commits as f64 > threshold {
+                dropped.insert(Line(line.as_ref().try_into()?));
+            }
+        }
+        if dropped.is_empty() {
+            return Ok(0);
+        }
+        for line in &dropped {
+            self.popular.insert(line.0, &[])?;
+            self.reverse.remove(line.0)?;
+        }
+        for x in self.forward.iter() {
+            let (oid, lines) = x?;
+            let kept = lines
+                .chunks_exact(20)
+                .filter(|x| !dropped.contains(&Line((*x).try_into().unwrap())))
+                .flatten()
+                .copied()
+                .collect::<Vec<u8>>();
+            if kept.len() != lines.len() {
+                self.forward.insert(oid, kept)?;
+            }
+        }
+        self.forward.flush()?;
+        Ok(dropped.len())
+    }
+
+    /// Check that the forward and reverse trees agree: if a commit is listed
+    /// under a line in `reverse`, then that line must be listed under the
+    /// commit in `forward`.  Also checks that every OID and line hash is 20
+    /// bytes long.  Each problem is printed; with `fix`, the bad entries are
+    /// removed.  Returns the number of problems found.
+    pub fn verify(&self, fix: bool) -> anyhow::Result<usize> {
+        let mut n_bad = 0;
+        let mut forward = HashMap::<[u8; 20], HashSet<[u8; 20]>>::new();
+        for x in self.forward.iter() {
+            let (oid, lines) = x?;
+            let oid_b: Option<[u8; 20]> = oid.as_ref().try_into().ok();
+            let Some(oid_b) = oid_b.filter(|_| lines.len() % 20 == 0) else {
+                println!(
+                    "forward: malformed entry for {} ({} bytes of lines)",
+                    hex(&oid),
+                    lines.len()
+                );
+                n_bad += 1;
+                if fix {
+                    // It'll be re-indexed on the next refresh
+                    self.forward.remove(&oid)?;
+                }
+                continue;
+            };
+            let lines = lines
+                .chunks_exact(20)
+                .map(|x| x.try_into().unwrap())
+                .collect();
+            forward.insert(oid_b, lines);
+        }
+        for x in self.reverse.iter() {
+            let (line, oids) = x?;
+            if line.len() != 20 || oids.len() % 20 != 0 {
+                println!(
+                    "reverse: malformed entry for {} ({} bytes of OIDs)",
+                    hex(&line),
+                    oids.len()
+                );
+                n_bad += 1;
+                if fix {
+                    self.reverse.remove(&line)?;
+                }
+                continue;
+            }
+            let line_b: [u8; 20] = line.as_ref().try_into()?;
+            let mut kept = vec![];
+            for oid in oids.chunks_exact(20) {
+                let oid: [u8; 20] = oid.try_into()?;
+                if forward.get(&oid).is_some_and(|x| x.contains(&line_b)) {
+                    kept.extend_from_slice(&oid);
+                } else {
+                    println!(
+                        "reverse: {} lists {}, but forward doesn't agree",
+                        hex(&line_b),
+                        hex(&oid)
+                    );
+                    n_bad += 1;
+                }
+            }
+            if fix && kept.len() != oids.len() {
+                if kept.is_empty() {
+                    self.reverse.remove(&line)?;
+                } else {
+                    self.reverse.insert(&line, kept)?;
+                }
+            }
+        }
+        self.reverse.flush()?;
+        self.forward.flush()?;
+        Ok(n_bad)
     }
 
-    // TODO: (perf) Drop very popular lines (eg. "" and "---")
     pub fn refresh(&self, repo: &Repository) -> anyhow::Result<()> {
         let time = std::time::Instant::now();
+        self.index_new_commits(repo, false)?;
+        tracing::info!("Refreshed the index in {:?}", time.elapsed());
+        Ok(())
+    }
+
+    /// Throw away the index and index every noted commit again.  Returns the
+    /// number of commits and lines indexed.
+    pub fn rebuild(&self, repo: &Repository, verbose: bool) -> anyhow::Result<(usize, usize)> {
+        self.forward.clear()?;
+        self.reverse.clear()?;
+        // The cached results came from the old index
+        self.dedup_cache.clear()?;
+        self.dedup_cache.flush()?;
+        self.index_new_commits(repo, verbose)
+    }
+
+    /// Index the noted commits which aren't in the index yet.  Returns the
+    /// number of commits and lines indexed.
+    fn index_new_commits(
+        &self,
+        repo: &Repository,
+        verbose: bool,
+    ) -> anyhow::Result<(usize, usize)> {
+        let reviews = reviewed_commits(repo);
+        let mut todo = vec![];
         for oid in recent_notes(repo)? {
-            if self.forward.get(oid.as_bytes())?.is_some() {
-                continue;
+            let indexed = self.forward.get(oid.as_bytes())?.is_some();
+            if reviews.is_indexable(&oid) {
+                if !indexed {
+                    todo.push(oid);
+                }
+            } else if indexed {
+                // Older versions of orpa indexed skipped and bookmarked
+                // commits
+                self.remove_commit(oid)?;
             }
-            let commit = repo.find_commit(oid)?;
-            let all_lines = commit_lines!(repo, &commit)
-                .map(|line| Line(Sha1::digest(line).into()))
-                .collect::<HashSet<_>>();
+        }
+        self.index_commits(repo, &todo, verbose)
+    }
+
+    /// Add the given commits to the index.  Returns the number of commits
+    /// and lines indexed.
+    fn index_commits(
+        &self,
+        repo: &Repository,
+        todo: &[Oid],
+        verbose: bool,
+    ) -> anyhow::Result<(usize, usize)> {
+        use rayon::prelude::*;
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        // Rendering and hashing the diffs is the slow part, so it's done in
+        // parallel.  `Repository` isn't `Sync`, so each thread opens its own.
+        let path = repo.path().to_owned();
+        let n_hashed = AtomicUsize::new(0);
+        let hashed = todo
+            .par_iter()
+            .map_init(
+                || Repository::open(&path),
+                |repo, &oid| {
+                    let repo = repo.as_ref().map_err(|e| anyhow!("{}", e))?;
+                    let commit = repo.find_commit(oid)?;
+                    let lines = commit_lines!(repo, &commit)
+                        .map(|line| Line(Sha1::digest(line).into()))
+                        .collect::<HashSet<_>>();
+                    let n = n_hashed.fetch_add(1, Ordering::Relaxed) + 1;
+                    if verbose && n.is_multiple_of(100) {
+                        eprintln!("Indexed {}/{} commits", n, todo.len());
+                    }
+                    anyhow::Ok((oid, lines))
+                },
+            )
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let n_commits = hashed.len();
+        let mut n_lines = 0;
+        for (oid, all_lines) in hashed {
             let mut all_lines_b = vec![];
             for digest in &all_lines {
+                if self.is_popular(*digest)? {
+                    continue;
+                }
                 self.reverse.merge(digest.0, oid)?;
                 all_lines_b.extend_from_slice(&digest.0);
+                n_lines += 1;
             }
             self.forward.insert(oid, all_lines_b)?;
         }
-        tracing::info!("Refreshed the index in {:?}", time.elapsed());
-        Ok(())
+        // The index lives in a static, so it's never dropped; make sure
+        // our changes hit the disk before we exit.
+        self.forward.flush()?;
+        Ok((n_commits, n_lines))
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// The email addresses listed in the commit's "Co-authored-by:" trailers
+pub fn co_author_emails(commit: &Commit) -> Vec<Vec<u8>> {
+    const PREFIX: &[u8] = b"co-authored-by:";
+    commit
+        .message_bytes()
+        .split(|&b| b == b'\n')
+        .filter(|line| {
+            line.len() > PREFIX.len() && line[..PREFIX.len()].eq_ignore_ascii_case(PREFIX)
+        })
+        .filter_map(|line| {
+            let start = line.iter().position(|&b| b == b'<')?;
+            let end = line.iter().rposition(|&b| b == b'>')?;
+            (start < end).then(|| line[start + 1..end].to_vec())
+        })
+        .collect()
+}
+
+/// Whether orpa.requireSignatures is set.  If so, reviewed commits which
+/// don't have a good signature are treated as unreviewed.
+fn require_signatures(repo: &Repository) -> bool {
+    static REQUIRE: OnceLock<bool> = OnceLock::new();
+    *REQUIRE.get_or_init(|| {
+        repo.config()
+            .and_then(|x| x.get_bool("orpa.requireSignatures"))
+            .unwrap_or(false)
+    })
+}
+
+/// Whether the commit has a signature.  It isn't checked; see
+/// `has_good_signature` for that.
+pub fn is_signed(repo: &Repository, oid: Oid) -> bool {
+    repo.extract_signature(&oid, None).is_ok()
+}
+
+/// What `git verify-commit` made of a commit's signature
+pub struct SignatureCheck {
+    pub validity: &'static str,
+    pub signer: String,
+    pub fingerprint: String,
+}
+
+pub fn check_signature(repo: &Repository, oid: Oid) -> anyhow::Result<SignatureCheck> {
+    let mut ret = SignatureCheck {
+        validity: "no-sig",
+        signer: String::new(),
+        fingerprint: String::new(),
+    };
+    if !is_signed(repo, oid) {
+        return Ok(ret);
     }
+    let out = std::process::Command::new("git")
+        .arg("--git-dir")
+        .arg(repo.path())
+        .args(["verify-commit", "--raw"])
+        .arg(oid.to_string())
+        .output()?;
+    // gpg's machine-readable status lines; see doc/DETAILS in gnupg
+    let status = String::from_utf8_lossy(&out.stderr);
+    let mut seen_status = false;
+    for line in status.lines() {
+        let Some(line) = line.strip_prefix("[GNUPG:] ") else {
+            continue;
+        };
+        let (keyword, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let validity = match keyword {
+            "GOODSIG" => "good",
+            "BADSIG" => "bad",
+            "EXPSIG" | "EXPKEYSIG" => "expired",
+            "REVKEYSIG" => "revoked",
+            "ERRSIG" => "unknown-key",
+            "VALIDSIG" => {
+                ret.fingerprint = rest.split(' ').next().unwrap_or("").to_owned();
+                continue;
+            }
+            _ => continue,
+        };
+        seen_status = true;
+        ret.validity = validity;
+        // "<keyid> <user id>", except ERRSIG, which has no user id
+        let mut words = rest.splitn(2, ' ');
+        let keyid = words.next().unwrap_or("");
+        if keyword != "ERRSIG" {
+            ret.signer = words.next().unwrap_or("").to_owned();
+        }
+        if ret.fingerprint.is_empty() {
+            ret.fingerprint = keyid.to_owned();
+        }
+    }
+    // eg. SSH signatures, which don't produce gpg status lines
+    if !seen_status {
+        ret.validity = if out.status.success() { "good" } else { "bad" };
+        ret.signer = status.lines().next().unwrap_or("").to_owned();
+    }
+    Ok(ret)
 }
 
-// TODO: Include addresses from the mailmap
-fn our_email(repo: &Repository) -> &'static [u8] {
-    static SIG: OnceLock<Vec<u8>> = OnceLock::new();
-    SIG.get_or_init(|| {
+/// Whether `git verify-commit` is happy with the commit's signature.  Bad,
+/// expired, and revoked signatures don't count, and nor do ones made with
+/// keys we don't have.  This runs gpg, so the answers are remembered.
+fn has_good_signature(repo: &Repository, oid: Oid) -> bool {
+    static CHECKED: LazyLock<Mutex<HashMap<Oid, bool>>> = LazyLock::new(Default::default);
+    if let Some(&x) = CHECKED.lock().unwrap().get(&oid) {
+        return x;
+    }
+    let good = match check_signature(repo, oid) {
+        Ok(x) => x.validity == "good",
+        Err(e) => {
+            warn!("{}: Couldn't check the signature: {}", oid, e);
+            false
+        }
+    };
+    CHECKED.lock().unwrap().insert(oid, good);
+    good
+}
+
+/// All the email addresses which belong to the current user.  This is the
+/// address from the user's signature, plus any aliases listed in the mailmap.
+pub fn our_email(repo: &Repository) -> &'static [Vec<u8>] {
+    static EMAILS: OnceLock<Vec<Vec<u8>>> = OnceLock::new();
+    EMAILS.get_or_init(|| {
         let sig = repo.signature().unwrap();
-        sig.email_bytes().to_vec()
+        let mut emails = vec![sig.email_bytes().to_vec()];
+        let mut f = || {
+            let mailmap = repo.mailmap()?;
+            let ours = mailmap.resolve_signature(&sig)?;
+            for (name, email) in mailmap_entries(repo)? {
+                let name = name.as_deref().or(sig.name()).unwrap_or("");
+                let alias = Signature::now(name, &email)?;
+                let resolved = mailmap.resolve_signature(&alias)?;
+                if resolved.email_bytes() == ours.email_bytes()
+                    && !emails.iter().any(|x| x == email.as_bytes())
+                {
+                    emails.push(email.into_bytes());
+                }
+            }
+            anyhow::Ok(())
+        };
+        if let Err(e) = f() {
+            warn!("Couldn't read the mailmap: {}", e);
+        }
+        info!(
+            "Our email addresses: {}",
+            emails.iter().map(|x| String::from_utf8_lossy(x)).join(", ")
+        );
+        emails
     })
-    .as_slice()
 }
 
-fn reviewed_commits(repo: &Repository) -> &'static HashMap<Oid, bool> {
-    static REVIEWS: OnceLock<HashMap<Oid, bool>> = OnceLock::new();
-    REVIEWS.get_or_init(|| {
+/// Every "Name <email>" pair mentioned in the mailmap.  The name is `None`
+/// if the email appears without one.
+///
+/// libgit2 doesn't let us enumerate the mailmap, so we have to read it
+/// ourselves.  We look in the same places as git: .mailmap in the worktree
+/// (or HEAD if the repo is bare), and the file pointed to by mailmap.file.
+fn mailmap_entries(repo: &Repository) -> anyhow::Result<Vec<(Option<String>, String)>> {
+    let mut contents = vec![];
+    match repo.workdir() {
+        Some(dir) => {
+            if let Ok(txt) = std::fs::read_to_string(dir.join(".mailmap")) {
+                contents.push(txt);
+            }
+        }
+        None => {
+            if let Ok(obj) = repo.revparse_single("HEAD:.mailmap") {
+                if let Ok(blob) = obj.peel_to_blob() {
+                    contents.push(String::from_utf8_lossy(blob.content()).into_owned());
+                }
+            }
+        }
+    }
+    if let Ok(path) = repo.config()?.get_path("mailmap.file") {
+        if let Ok(txt) = std::fs::read_to_string(path) {
+            contents.push(txt);
+        }
+    }
+    let mut entries = vec![];
+    for line in contents.iter().flat_map(|x| x.lines()) {
+        let line = line.split('#').next().unwrap_or("");
+        let mut rest = line;
+        while let Some((name, tail)) = rest.split_once('<') {
+            let Some((email, tail)) = tail.split_once('>') else {
+                break;
+            };
+            let name = Some(name.trim()).filter(|x| !x.is_empty());
+            entries.push((name.map(|x| x.to_owned()), email.trim().to_owned()));
+            rest = tail;
+        }
+    }
+    Ok(entries)
+}
+
+/// Rewrite the sled DB at `path` to get rid of the garbage which builds up
+/// over time.  sled never shrinks its files, so the only way to do this is
+/// to export everything into a fresh DB and swap it in.
+pub fn compact_db(path: &Path, dry_run: bool, compact_index: bool) -> anyhow::Result<()> {
+    use indicatif::HumanBytes;
+    let db = sled::open(path)?;
+    let before = db.size_on_disk()?;
+    if dry_run {
+        println!("{} is {}", path.display(), HumanBytes(before));
+        return Ok(());
+    }
+    if compact_index {
+        let n = LineIdx::from_db(&db)?.compact(0.5)?;
+        println!("Dropped {} popular lines from the index", n);
+    }
+    db.flush()?;
+
+    let tmp_path = path.join("compact.tmp");
+    if tmp_path.exists() {
+        // Left over from an interrupted run
+        std::fs::remove_dir_all(&tmp_path)?;
+    }
+    {
+        let new_db = sled::open(&tmp_path)?;
+        new_db.import(db.export());
+        new_db.flush()?;
+    }
+    drop(db);
+
+    // Swap sled's files for the new ones, leaving everything else in the
+    // directory (the MR cache, etc.) alone
+    let is_sled_file =
+        |name: &str| matches!(name, "conf" | "db" | "blobs") || name.starts_with("snap.");
+    for entry in std::fs::read_dir(path)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        if !is_sled_file(&name.to_string_lossy()) {
+            continue;
+        }
+        if entry.file_type()?.is_dir() {
+            std::fs::remove_dir_all(entry.path())?;
+        } else {
+            std::fs::remove_file(entry.path())?;
+        }
+    }
+    for entry in std::fs::read_dir(&tmp_path)? {
+        let entry = entry?;
+        std::fs::rename(entry.path(), path.join(entry.file_name()))?;
+    }
+    std::fs::remove_dir(&tmp_path)?;
+    let after = sled::open(path)?.size_on_disk()?;
+    println!(
+        "Compacted {} from {} to {} (reclaimed {})",
+        path.display(),
+        HumanBytes(before),
+        HumanBytes(after),
+        HumanBytes(before.saturating_sub(after))
+    );
+    Ok(())
+}
+
+struct Reviews {
+    /// All commits with notes, and what the note says: `Checkpoint`,
+    /// `Skipped`, `Bookmarked`, or `Reviewed` for anything else.  Commits
+    /// whose only note is a branch checkpoint aren't included.
+    commits: HashMap<Oid, Status>,
+    /// Checkpoints which only apply to a particular branch.  These are
+    /// loaded lazily, since we only ever care about one branch.
+    branch_checkpoints: Mutex<HashMap<String, Arc<HashSet<Oid>>>>,
+}
+
+fn reviewed_commits(repo: &Repository) -> Arc<Reviews> {
+    let mut reviews = REVIEWS.lock().unwrap();
+    let reviews = reviews.get_or_insert_with(|| {
         let f = || {
+            let notes_tip = notes_tip(repo)?;
+            if let Some(tip) = notes_tip {
+                match load_review_cache(repo, tip) {
+                    Ok(Some(reviews)) => {
+                        info!("Loaded {} reviews from the cache", reviews.len());
+                        return Ok(reviews);
+                    }
+                    Ok(None) => (),
+                    Err(e) => warn!("Couldn't read the review cache: {}", e),
+                }
+            }
+
             let mut wtr = repo.blob_writer(None)?;
             wtr.write_all(b"checkpoint")?;
             let checkpoint_oid = wtr.commit()?;
             info!("Checkpoint OID is {}", checkpoint_oid);
 
+            // Lots of commits share a note (eg. checkpoints), so each blob
+            // is only read once
+            let mut by_blob = HashMap::new();
             let mut reviews = HashMap::new();
-            for x in repo.notes(notes_ref())? {
-                let (note_oid, commit_oid) = x?;
-                reviews.insert(commit_oid, note_oid == checkpoint_oid);
+            for (note_oid, commit_oid) in all_notes(repo)? {
+                let status = match by_blob.get(&note_oid) {
+                    Some(&x) => x,
+                    None if note_oid == checkpoint_oid => Some(Status::Checkpoint),
+                    None => {
+                        let blob = repo.find_blob(note_oid)?;
+                        let x = note_status(&String::from_utf8_lossy(blob.content()));
+                        by_blob.insert(note_oid, x);
+                        x
+                    }
+                };
+                if let Some(status) = status {
+                    reviews.insert(commit_oid, status);
+                }
             }
             info!("Scanned {} reviews", reviews.len());
+            if let Some(tip) = notes_tip {
+                if let Err(e) = save_review_cache(repo, tip, &reviews) {
+                    warn!("Couldn't write the review cache: {}", e);
+                }
+            }
             anyhow::Ok(reviews)
         };
-        f().unwrap()
-    })
+        Arc::new(Reviews {
+            commits: f().unwrap(),
+            branch_checkpoints: Mutex::new(HashMap::new()),
+        })
+    });
+    reviews.clone()
+}
+
+/// The commit which the notes ref points to, if it exists
+fn notes_tip(repo: &Repository) -> anyhow::Result<Option<Oid>> {
+    let notes_ref = match notes_ref() {
+        Some(x) => x.to_owned(),
+        None => repo.note_default_ref()?,
+    };
+    match repo.refname_to_id(&notes_ref) {
+        Ok(x) => Ok(Some(x)),
+        Err(e) if e.code() == ErrorCode::NotFound => Ok(None),
+        Err(e) => Err(e.into()),
+    }
+}
+
+/// The review cache is a list of 21-byte records: a commit OID followed by
+/// the `Status` of its note.  It's only valid while the notes ref still
+/// points at the commit recorded in review_cache.oid (after the format
+/// version, so that caches written by older versions of orpa are ignored).
+fn review_cache_paths(repo: &Repository) -> (PathBuf, PathBuf) {
+    let dir = db_path(repo);
+    (dir.join("review_cache.bin"), dir.join("review_cache.oid"))
+}
+
+fn review_cache_key(notes_tip: Oid) -> String {
+    const FORMAT: u32 = 3;
+    format!("{} {}", FORMAT, notes_tip)
+}
+
+fn load_review_cache(
+    repo: &Repository,
+    notes_tip: Oid,
+) -> anyhow::Result<Option<HashMap<Oid, Status>>> {
+    let (bin_path, oid_path) = review_cache_paths(repo);
+    match std::fs::read_to_string(&oid_path) {
+        Ok(x) if x.trim() == review_cache_key(notes_tip) => (),
+        _ => return Ok(None),
+    }
+    let bytes = std::fs::read(&bin_path)?;
+    if bytes.len() % 21 != 0 {
+        return Err(anyhow!("{}: truncated", bin_path.display()));
+    }
+    let mut reviews = HashMap::with_capacity(bytes.len() / 21);
+    for record in bytes.chunks_exact(21) {
+        let status = record[20] as usize;
+        if status >= Status::LENGTH {
+            return Err(anyhow!("{}: bad status {}", bin_path.display(), status));
+        }
+        reviews.insert(Oid::from_bytes(&record[..20])?, Status::from_usize(status));
+    }
+    Ok(Some(reviews))
+}
+
+fn save_review_cache(
+    repo: &Repository,
+    notes_tip: Oid,
+    reviews: &HashMap<Oid, Status>,
+) -> anyhow::Result<()> {
+    let (bin_path, oid_path) = review_cache_paths(repo);
+    if !db_path(repo).is_dir() {
+        // We don't create the DB just for this
+        return Ok(());
+    }
+    let mut bytes = Vec::with_capacity(reviews.len() * 21);
+    for (oid, status) in reviews {
+        bytes.extend_from_slice(oid.as_bytes());
+        bytes.push(status.into_usize() as u8);
+    }
+    std::fs::write(&bin_path, bytes)?;
+    // Write the OID last, so a half-written cache is never considered valid
+    std::fs::write(&oid_path, review_cache_key(notes_tip))?;
+    Ok(())
+}
+
+impl Reviews {
+    /// Whether the commit's note says it was reviewed, so that commits with
+    /// the same diff can count as reviewed too
+    fn is_indexable(&self, oid: &Oid) -> bool {
+        matches!(
+            self.commits.get(oid),
+            Some(Status::Reviewed | Status::Checkpoint)
+        )
+    }
+}
+
+/// Commits which have been checkpointed for the given branch only
+fn branch_checkpoints(repo: &Repository, branch: &str) -> anyhow::Result<Arc<HashSet<Oid>>> {
+    let reviews = reviewed_commits(repo);
+    let mut cache = reviews.branch_checkpoints.lock().unwrap();
+    if let Some(x) = cache.get(branch) {
+        return Ok(x.clone());
+    }
+    let checkpoint_oid =
+        Oid::hash_object(ObjectType::Blob, checkpoint_note(Some(branch)).as_bytes())?;
+    let mut checkpoints = HashSet::new();
+    for (note_oid, commit_oid) in all_notes(repo)? {
+        if note_oid == checkpoint_oid {
+            checkpoints.insert(commit_oid);
+        }
+    }
+    let checkpoints = Arc::new(checkpoints);
+    cache.insert(branch.to_owned(), checkpoints.clone());
+    Ok(checkpoints)
+}
+
+/// The text of a checkpoint note.  Global checkpoints apply when walking any
+/// branch; branch-specific ones only apply to the named branch.
+pub fn checkpoint_note(branch: Option<&str>) -> String {
+    match branch {
+        Some(branch) => format!("checkpoint:{}", branch),
+        None => "checkpoint".into(),
+    }
+}
+
+/// Which branch's checkpoints apply when walking the given range?  For
+/// "foo..HEAD" it's "foo"; if there's no range, it's the current branch.
+fn checkpoint_branch(repo: &Repository, range: Option<&String>) -> Option<String> {
+    match range {
+        Some(range) => range.strip_suffix("..HEAD").map(|x| x.to_owned()),
+        None => {
+            let head = repo.head().ok()?;
+            if head.is_branch() {
+                head.shorthand().map(|x| x.to_owned())
+            } else {
+                None
+            }
+        }
+    }
+}
+
+static REVIEWS: Mutex<Option<Arc<Reviews>>> = Mutex::new(None);
+
+/// Forget the cached set of reviewed commits, so that notes which were
+/// added since it was built are picked up by the next `lookup`.
+pub fn invalidate_reviews(repo: &Repository) {
+    *REVIEWS.lock().unwrap() = None;
+    let (bin_path, oid_path) = review_cache_paths(repo);
+    let _ = std::fs::remove_file(oid_path);
+    let _ = std::fs::remove_file(bin_path);
+}
+
+/// Does the note only say that people skipped the commit?
+fn is_skip_note(note: &str) -> bool {
+    let trailers = parse_trailers(note);
+    !trailers.is_empty()
+        && trailers.iter().all(|x| x.verb == "Skipped")
+        && !note.lines().any(|x| x.trim() == "checkpoint")
+}
+
+/// Does the note only say that people want to come back to the commit later?
+pub fn is_bookmark_note(note: &str) -> bool {
+    let trailers = parse_trailers(note);
+    !trailers.is_empty()
+        && trailers.iter().all(|x| x.verb == "Bookmarked")
+        && !note.lines().any(|x| x.trim() == "checkpoint")
+}
+
+/// Whether commits without notes should be compared against reviewed ones,
+/// via --dedup or `dedup = true` in the config file
+pub fn dedup_enabled() -> bool {
+    OPTS.dedup || file_config().orpa.dedup == Some(true)
+}
+
+/// What a note means for the commit it's attached to.  Branch checkpoints
+/// don't mean anything outside their branch, so they give `None`.
+fn note_status(note: &str) -> Option<Status> {
+    if note == checkpoint_note(None) {
+        Some(Status::Checkpoint)
+    } else if is_branch_checkpoint_note(note) {
+        None
+    } else if is_skip_note(note) {
+        Some(Status::Skipped)
+    } else if is_bookmark_note(note) {
+        Some(Status::Bookmarked)
+    } else {
+        Some(Status::Reviewed)
+    }
+}
+
+/// Is the note a checkpoint for a particular branch?
+fn is_branch_checkpoint_note(note: &str) -> bool {
+    note.strip_prefix("checkpoint:")
+        .is_some_and(|branch| !branch.is_empty() && !branch.contains('\n'))
 }
 
 pub fn lookup(repo: &Repository, oid: Oid) -> anyhow::Result<Status> {
-    match reviewed_commits(repo).get(&oid) {
-        Some(true) => Ok(Status::Checkpoint),
-        Some(false) => Ok(Status::Reviewed),
+    match reviewed_commits(repo).commits.get(&oid) {
+        Some(Status::Reviewed) if require_signatures(repo) && !has_good_signature(repo, oid) => {
+            Ok(Status::New)
+        }
+        Some(&status) => Ok(status),
         None => {
             let commit = repo.find_commit(oid)?;
-            if commit.author().email_bytes() == our_email(repo) {
+            let ours = our_email(repo);
+            if ours.iter().any(|x| x == commit.author().email_bytes())
+                || co_author_emails(&commit).iter().any(|x| ours.contains(x))
+            {
                 Ok(Status::Ours)
             } else if commit.parent_count() > 1 {
                 Ok(Status::Merge)
             } else {
                 let mut reviewed = false;
-                if OPTS.dedup {
-                    let digest = commit_diff_digest(repo, &commit)?;
-                    for (other_oid, _) in similiar_commits(repo, &commit)?
-                        .into_iter()
-                        .filter(|(_, ddiff)| ddiff.score() == 1.)
-                    {
-                        let other = repo.find_commit(other_oid)?;
-                        let other_digest = commit_diff_digest(repo, &other)?;
-                        if digest == other_digest {
-                            reviewed = true;
-                            break;
-                        }
-                    }
+                if dedup_enabled() {
+                    reviewed = is_deduped(
+                        repo,
+                        get_idx(repo)?,
+                        &reviewed_commits(repo),
+                        notes_tip(repo)?,
+                        &commit,
+                        OPTS.dedup_threshold.unwrap_or(1.),
+                    )?;
                 }
                 if reviewed {
                     tracing::info!("Found a commit that matches!");
@@ -276,10 +1131,85 @@ pub fn lookup(repo: &Repository, oid: Oid) -> anyhow::Result<Status> {
     }
 }
 
+/// Like `is_duplicate`, but exact matches are cached until the notes change
+fn is_deduped(
+    repo: &Repository,
+    idx: &LineIdx,
+    reviews: &Reviews,
+    notes_tip: Option<Oid>,
+    commit: &Commit,
+    threshold: f64,
+) -> anyhow::Result<bool> {
+    let digest = commit_diff_digest(repo, commit)?;
+    // Near-matches depend on the threshold, so only exact matches are cached
+    if threshold < 1. {
+        return is_duplicate(repo, idx, reviews, commit, digest, threshold);
+    }
+    let cache = idx.valid_dedup_cache(notes_tip)?;
+    if let Some(x) = cache.get(digest.0)? {
+        return Ok(x.as_ref() == [1]);
+    }
+    let ret = is_duplicate(repo, idx, reviews, commit, digest, threshold)?;
+    cache.insert(digest.0, &[ret as u8])?;
+    Ok(ret)
+}
+
+/// Is there a reviewed commit whose diff is (close enough to) the same?
+fn is_duplicate(
+    repo: &Repository,
+    idx: &LineIdx,
+    reviews: &Reviews,
+    commit: &Commit,
+    digest: Line,
+    threshold: f64,
+) -> anyhow::Result<bool> {
+    for (other_oid, _) in similar_in(repo, idx, reviews, commit)?
+        .into_iter()
+        .filter(|(_, ddiff)| ddiff.score() >= threshold)
+    {
+        if threshold < 1. {
+            // The user is happy with a near-match
+            return Ok(true);
+        }
+        // A perfect score only means that the commits contain the same
+        // lines; make sure the diffs are really identical.
+        let other = repo.find_commit(other_oid)?;
+        if digest == commit_diff_digest(repo, &other)? {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
 pub fn walk_new(
     repo: &Repository,
     range: Option<&String>,
     mut f: impl FnMut(Oid),
+) -> anyhow::Result<()> {
+    walk_new_with_opts(repo, range, false, |oid, _| f(oid))
+}
+
+/// Like `walk_new`, but optionally yields merge commits too.  The callback
+/// gets the status, so it can tell them apart.
+pub fn walk_new_with_opts(
+    repo: &Repository,
+    range: Option<&String>,
+    include_merges: bool,
+    mut f: impl FnMut(Oid, Status),
+) -> anyhow::Result<()> {
+    walk_statuses(repo, range, |oid, status| match status {
+        Status::New => f(oid, status),
+        Status::Merge if include_merges => f(oid, status),
+        _ => (),
+    })
+}
+
+/// Walk the range (or HEAD), stopping at the first checkpoint, and look up
+/// the status of every commit
+pub fn walk_statuses(
+    repo: &Repository,
+    range: Option<&String>,
+    mut f: impl FnMut(Oid, Status),
 ) -> anyhow::Result<()> {
     let mut walk = repo.revwalk()?;
     if let Some(range) = range {
@@ -287,13 +1217,18 @@ pub fn walk_new(
     } else {
         walk.push_head()?;
     }
+    let branch_checkpoints = match checkpoint_branch(repo, range) {
+        Some(branch) => branch_checkpoints(repo, &branch)?,
+        None => Arc::default(),
+    };
     for oid in walk {
         let oid = oid?;
-        let status = lookup(repo, oid)?;
-        match status {
-            Status::New => f(oid),
+        if branch_checkpoints.contains(&oid) {
+            break;
+        }
+        match lookup(repo, oid)? {
             Status::Checkpoint => break,
-            _ => (),
+            status => f(oid, status),
         }
     }
     Ok(())
@@ -359,7 +1294,7 @@ pub fn commit_diff_digest(repo: &Repository, c: &Commit) -> anyhow::Result<Line>
     Ok(Line(Sha1::digest(diff).into()))
 }
 
-pub fn empty_tree(repo: &Repository) -> anyhow::Result<Tree> {
+pub fn empty_tree(repo: &Repository) -> anyhow::Result<Tree<'_>> {
     let oid = repo.treebuilder(None)?.write()?;
     Ok(repo.find_tree(oid)?)
 }
@@ -392,8 +1327,103 @@ pub fn show_commit_with_diffstat(repo: &Repository, oid: Oid) -> anyhow::Result<
 #[derive(Copy, Clone, PartialEq, Eq, Debug, Enum)]
 pub enum Status {
     Reviewed,
+    /// Someone looked at the commit, but decided not to review it
+    Skipped,
+    /// Someone saved the commit to review later
+    Bookmarked,
     Checkpoint,
     Ours,
     Merge,
     New,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Commit a file on top of `parent` (or as a root commit)
+    fn commit_file(
+        repo: &Repository,
+        parent: Option<Oid>,
+        path: &str,
+        contents: &str,
+        message: &str,
+    ) -> Oid {
+        let parent = parent.map(|x| repo.find_commit(x).unwrap());
+        let base = parent.as_ref().map(|x| x.tree().unwrap());
+        let mut tree = repo.treebuilder(base.as_ref()).unwrap();
+        let blob = repo.blob(contents.as_bytes()).unwrap();
+        tree.insert(path, blob, 0o100644).unwrap();
+        let tree = repo.find_tree(tree.write().unwrap()).unwrap();
+        let sig = Signature::now("Joe Smith", "joe@smith.net").unwrap();
+        let parents = parent.iter().collect::<Vec<_>>();
+        repo.commit(None, &sig, &sig, message, &tree, &parents)
+            .unwrap()
+    }
+
+    fn reviews(reviewed: &[Oid]) -> Reviews {
+        Reviews {
+            commits: reviewed.iter().map(|&x| (x, Status::Reviewed)).collect(),
+            branch_checkpoints: Mutex::new(HashMap::new()),
+        }
+    }
+
+    #[test]
+    fn branch_checkpoint_isnt_a_review() {
+        assert_eq!(note_status("checkpoint"), Some(Status::Checkpoint));
+        assert_eq!(note_status(&checkpoint_note(Some("main"))), None);
+        assert_eq!(
+            note_status("checkpoint:main\n\nReviewed-by: Joe Smith <joe@smith.net>"),
+            Some(Status::Reviewed)
+        );
+    }
+
+    #[test]
+    fn dedup_cache_is_hit_on_second_lookup() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path().join("repo")).unwrap();
+        let idx = LineIdx::open(&dir.path().join("db")).unwrap();
+        let base = commit_file(&repo, None, "README", "hello\n", "Initial commit");
+        let original = commit_file(&repo, Some(base), "foo", "foo\nbar\n", "Add foo");
+        // The same change, on top of something else
+        let other_base = commit_file(&repo, Some(base), "LICENSE", "none\n", "Add a license");
+        let copy = commit_file(&repo, Some(other_base), "foo", "foo\nbar\n", "Add foo");
+        let copy = repo.find_commit(copy).unwrap();
+        idx.index_commits(&repo, &[original], false).unwrap();
+        let reviews = reviews(&[original]);
+
+        assert!(is_deduped(&repo, &idx, &reviews, None, &copy, 1.).unwrap());
+        // Now only the cache knows about the original
+        idx.remove_commit(original).unwrap();
+        let digest = commit_diff_digest(&repo, &copy).unwrap();
+        assert!(!is_duplicate(&repo, &idx, &reviews, &copy, digest, 1.).unwrap());
+        assert!(is_deduped(&repo, &idx, &reviews, None, &copy, 1.).unwrap());
+        // ...until the notes change
+        assert!(!is_deduped(&repo, &idx, &reviews, Some(base), &copy, 1.).unwrap());
+    }
+
+    #[test]
+    fn near_identical_commit_counts_as_reviewed() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(dir.path().join("repo")).unwrap();
+        let idx = LineIdx::open(&dir.path().join("db")).unwrap();
+        let base = commit_file(&repo, None, "README", "hello\n", "Initial commit");
+        let lines = (0..100)
+            .map(|i| format!("line {}\n", i))
+            .collect::<Vec<_>>();
+        let original = commit_file(&repo, Some(base), "foo", &lines.concat(), "Add foo");
+        // One line is different
+        let mut tweaked = lines.clone();
+        tweaked[50] = "line fifty\n".into();
+        let tweaked = commit_file(&repo, Some(base), "foo", &tweaked.concat(), "Add foo");
+        let tweaked = repo.find_commit(tweaked).unwrap();
+        idx.index_commits(&repo, &[original], false).unwrap();
+        let reviews = reviews(&[original]);
+        let digest = commit_diff_digest(&repo, &tweaked).unwrap();
+
+        let is_dup =
+            |threshold| is_duplicate(&repo, &idx, &reviews, &tweaked, digest, threshold).unwrap();
+        assert!(is_dup(0.95));
+        assert!(!is_dup(1.));
+    }
+}
@@ -0,0 +1,307 @@
+use crate::mr_db::{MRWithVersions, VersionInfo};
+use crate::review_db::*;
+use crate::{cached_mrs, git_time_to_chrono, parse_since, OPTS};
+use chrono::{DateTime, Duration, FixedOffset, Utc};
+use enum_map::EnumMap;
+use git2::{Oid, Repository};
+use itertools::Itertools;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::io::Write;
+use tabwriter::TabWriter;
+
+#[derive(Default, Debug)]
+pub struct Stats {
+    /// Number of commits in the range, by status.  Commits which are
+    /// ancestors of a checkpoint are counted as `Checkpoint`.
+    pub by_status: EnumMap<Status, usize>,
+    /// Number of commits in the range which each reviewer has left a note on
+    pub reviewers: BTreeMap<String, usize>,
+    pub oldest_review: Option<DateTime<FixedOffset>>,
+    pub newest_review: Option<DateTime<FixedOffset>>,
+    /// For reviewed commits which were part of an MR: how long after the
+    /// MR version containing them was first seen did they get reviewed?
+    pub review_latencies: Vec<Duration>,
+}
+
+impl Stats {
+    pub fn total(&self) -> usize {
+        self.by_status.values().sum()
+    }
+
+    pub fn commits_per_reviewer(&self) -> f64 {
+        if self.reviewers.is_empty() {
+            return 0.;
+        }
+        self.reviewers.values().sum::<usize>() as f64 / self.reviewers.len() as f64
+    }
+
+    pub fn mean_review_latency(&self) -> Option<Duration> {
+        if self.review_latencies.is_empty() {
+            return None;
+        }
+        let total = self.review_latencies.iter().sum::<Duration>();
+        Some(total / self.review_latencies.len() as i32)
+    }
+}
+
+pub fn compute(
+    repo: &Repository,
+    range: Option<&String>,
+    author: Option<&str>,
+) -> anyhow::Result<Stats> {
+    // Find where the checkpoint is before filtering by author: it may well
+    // have been written by someone else.  Anything not visited here is at or
+    // behind the checkpoint.
+    let mut statuses = HashMap::new();
+    walk_statuses(repo, range, |oid, status| {
+        statuses.insert(oid, status);
+    })?;
+    let mut walk = repo.revwalk()?;
+    if let Some(range) = range {
+        walk.push_range(range)?;
+    } else {
+        walk.push_head()?;
+    }
+    let note_times = note_times(repo)?;
+    let first_seen = commits_first_seen(repo)?;
+    let mut stats = Stats::default();
+    for oid in walk {
+        let oid = oid?;
+        if let Some(author) = author {
+            let commit = repo.find_commit(oid)?;
+            let sig = commit.author();
+            if sig.name() != Some(author) && sig.email() != Some(author) {
+                continue;
+            }
+        }
+        let Some(&status) = statuses.get(&oid) else {
+            stats.by_status[Status::Checkpoint] += 1;
+            continue;
+        };
+        stats.by_status[status] += 1;
+
+        let Some(note) = get_note(repo, oid)? else {
+            continue;
+        };
+        // Skipping or bookmarking a commit isn't a review
+        let trailers = parse_trailers(&note)
+            .into_iter()
+            .filter(|x| x.verb == "Reviewed")
+            .collect::<Vec<_>>();
+        if trailers.is_empty() {
+            continue;
+        }
+        if let Some(&when) = note_times.get(&oid) {
+            let when = git_time_to_chrono(when);
+            stats.oldest_review = Some(stats.oldest_review.map_or(when, |x| x.min(when)));
+            stats.newest_review = Some(stats.newest_review.map_or(when, |x| x.max(when)));
+            if let Some(seen) = first_seen.get(&oid) {
+                let latency = when.with_timezone(&Utc) - *seen;
+                // Commits can be reviewed before they're put up in an MR
+                if latency >= Duration::zero() {
+                    stats.review_latencies.push(latency);
+                }
+            }
+        }
+        for trailer in trailers {
+            *stats.reviewers.entry(trailer.name).or_default() += 1;
+        }
+    }
+    Ok(stats)
+}
+
+pub fn stats(
+    repo: &Repository,
+    range: Option<String>,
+    author: Option<String>,
+) -> anyhow::Result<()> {
+    let stats = compute(repo, range.as_ref(), author.as_deref())?;
+    if OPTS.json {
+        let json = serde_json::json!({
+            "total": stats.total(),
+            "reviewed": stats.by_status[Status::Reviewed],
+            "skipped": stats.by_status[Status::Skipped],
+            "bookmarked": stats.by_status[Status::Bookmarked],
+            "unreviewed": stats.by_status[Status::New],
+            "checkpoint": stats.by_status[Status::Checkpoint],
+            "ours": stats.by_status[Status::Ours],
+            "merge": stats.by_status[Status::Merge],
+            "reviewers": stats.reviewers.len(),
+            "commits_per_reviewer": stats.commits_per_reviewer(),
+            "oldest_review": stats.oldest_review.map(|x| x.to_rfc3339()),
+            "newest_review": stats.newest_review.map(|x| x.to_rfc3339()),
+            "mean_review_latency_secs": stats.mean_review_latency().map(|x| x.num_seconds()),
+        });
+        println!("{}", json);
+        return Ok(());
+    }
+    let mut tw = TabWriter::new(std::io::stdout());
+    writeln!(tw, "Commits:\t{}", stats.total())?;
+    writeln!(tw, "  reviewed:\t{}", stats.by_status[Status::Reviewed])?;
+    writeln!(tw, "  skipped:\t{}", stats.by_status[Status::Skipped])?;
+    writeln!(tw, "  bookmarked:\t{}", stats.by_status[Status::Bookmarked])?;
+    writeln!(tw, "  unreviewed:\t{}", stats.by_status[Status::New])?;
+    writeln!(
+        tw,
+        "  checkpointed:\t{}",
+        stats.by_status[Status::Checkpoint]
+    )?;
+    writeln!(tw, "  your own:\t{}", stats.by_status[Status::Ours])?;
+    writeln!(tw, "  merges:\t{}", stats.by_status[Status::Merge])?;
+    writeln!(tw, "Reviewers:\t{}", stats.reviewers.len())?;
+    writeln!(
+        tw,
+        "Commits per reviewer:\t{:.1}",
+        stats.commits_per_reviewer()
+    )?;
+    if let Some(x) = stats.oldest_review {
+        writeln!(tw, "Oldest review:\t{}", x)?;
+    }
+    if let Some(x) = stats.newest_review {
+        writeln!(tw, "Newest review:\t{}", x)?;
+    }
+    if let Some(x) = stats.mean_review_latency() {
+        let x = timeago::Formatter::new().ago("").convert(x.to_std()?);
+        writeln!(tw, "Mean time to review:\t{}", x.trim())?;
+    }
+    tw.flush()?;
+    Ok(())
+}
+
+#[derive(Default, Debug)]
+pub struct ReviewerStats {
+    pub name: String,
+    pub email: String,
+    pub n_reviews: usize,
+    pub first_review: Option<DateTime<FixedOffset>>,
+    pub last_review: Option<DateTime<FixedOffset>>,
+    /// The MRs containing commits which this person reviewed
+    pub mrs: BTreeSet<u64>,
+}
+
+/// Per-reviewer statistics, covering every note in the repo.  Reviewers
+/// are identified by email, and sorted by number of reviews (descending).
+pub fn compute_audit(
+    repo: &Repository,
+    since: Option<DateTime<FixedOffset>>,
+) -> anyhow::Result<Vec<ReviewerStats>> {
+    let note_times = note_times(repo)?;
+    let mr_commits = mr_commits(repo)?;
+    let mut reviewers = HashMap::<String, ReviewerStats>::new();
+    for (_, oid) in all_notes(repo)? {
+        let when = note_times.get(&oid).map(|&x| git_time_to_chrono(x));
+        if since.is_some() && when < since {
+            continue;
+        }
+        let Some(note) = get_note(repo, oid)? else {
+            continue;
+        };
+        for trailer in parse_trailers(&note)
+            .into_iter()
+            .filter(|x| x.verb == "Reviewed")
+        {
+            let key = if trailer.email.is_empty() {
+                trailer.name.clone()
+            } else {
+                trailer.email.clone()
+            };
+            let entry = reviewers.entry(key).or_insert_with(|| ReviewerStats {
+                name: trailer.name,
+                email: trailer.email,
+                ..ReviewerStats::default()
+            });
+            entry.n_reviews += 1;
+            if let Some(when) = when {
+                entry.first_review = Some(entry.first_review.map_or(when, |x| x.min(when)));
+                entry.last_review = Some(entry.last_review.map_or(when, |x| x.max(when)));
+            }
+            if let Some(iids) = mr_commits.get(&oid) {
+                entry.mrs.extend(iids);
+            }
+        }
+    }
+    let mut reviewers = reviewers.into_values().collect::<Vec<_>>();
+    reviewers.sort_by(|x, y| y.n_reviews.cmp(&x.n_reviews).then(x.name.cmp(&y.name)));
+    Ok(reviewers)
+}
+
+/// Call `f` on each commit in each version of each cached MR
+fn for_each_mr_commit(
+    repo: &Repository,
+    mut f: impl FnMut(Oid, &MRWithVersions, &VersionInfo),
+) -> anyhow::Result<()> {
+    // If we've never fetched, there are no MRs
+    let mrs = cached_mrs(repo).unwrap_or_default();
+    for mr in &mrs {
+        for ver in mr.versions.values() {
+            let mut walk = repo.revwalk()?;
+            if walk
+                .push_range(&format!("{}..{}", &ver.base.0, &ver.head.0))
+                .is_err()
+            {
+                // The commits are missing
+                continue;
+            }
+            for oid in walk {
+                f(oid?, mr, ver);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Which (cached) MRs is each commit a part of?
+fn mr_commits(repo: &Repository) -> anyhow::Result<HashMap<Oid, BTreeSet<u64>>> {
+    let mut ret = HashMap::<Oid, BTreeSet<u64>>::new();
+    for_each_mr_commit(repo, |oid, mr, _| {
+        ret.entry(oid).or_default().insert(mr.mr.iid.0);
+    })?;
+    Ok(ret)
+}
+
+/// When did each commit first show up in an MR?
+fn commits_first_seen(repo: &Repository) -> anyhow::Result<HashMap<Oid, DateTime<Utc>>> {
+    let mut ret = HashMap::<Oid, DateTime<Utc>>::new();
+    for_each_mr_commit(repo, |oid, _, ver| {
+        if let Some(t) = ver.created_at {
+            let x = ret.entry(oid).or_insert(t);
+            *x = (*x).min(t);
+        }
+    })?;
+    Ok(ret)
+}
+
+pub fn audit(
+    repo: &Repository,
+    reviewer: Option<String>,
+    since: Option<String>,
+) -> anyhow::Result<()> {
+    let since = since
+        .as_deref()
+        .map(parse_since)
+        .transpose()?
+        .map(|x| x.fixed_offset());
+    let mut reviewers = compute_audit(repo, since)?;
+    if let Some(reviewer) = reviewer {
+        reviewers.retain(|x| x.name == reviewer || x.email == reviewer);
+    }
+    let fmt_date = |x: Option<DateTime<FixedOffset>>| {
+        x.map_or(String::new(), |x| x.format("%Y-%m-%d").to_string())
+    };
+    let mut tw = TabWriter::new(std::io::stdout());
+    writeln!(tw, "Reviewer\tReviews\tFirst\tLast\tMRs")?;
+    for x in &reviewers {
+        writeln!(
+            tw,
+            "{} <{}>\t{}\t{}\t{}\t{}",
+            x.name,
+            x.email,
+            x.n_reviews,
+            fmt_date(x.first_review),
+            fmt_date(x.last_review),
+            x.mrs.iter().map(|x| format!("!{}", x)).join(" "),
+        )?;
+    }
+    tw.flush()?;
+    Ok(())
+}
@@ -0,0 +1,356 @@
+use crate::mr_db::MRWithVersions;
+use crate::review_db::*;
+use crate::{
+    cached_open_mrs, config, fmt_mr_ref, is_assigned, load_watchlist, mr_paths, mr_unreviewed_count,
+};
+use git2::{Diff, Repository};
+use globset::GlobSet;
+use std::fmt;
+use std::io::Write;
+use tabwriter::TabWriter;
+use tracing::*;
+
+/// Diffs with at most this many changed lines count as small
+const SMALL_MR: usize = 200;
+const SMALL_COMMIT: usize = 50;
+
+/// Why something might deserve attention
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Reason {
+    Assigned,
+    Watchlist,
+    RecentlyUpdated,
+    SmallDiff,
+}
+
+impl Reason {
+    fn weight(self) -> u32 {
+        match self {
+            Reason::Assigned => 4,
+            Reason::Watchlist => 2,
+            Reason::RecentlyUpdated => 1,
+            Reason::SmallDiff => 1,
+        }
+    }
+}
+
+impl fmt::Display for Reason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Reason::Assigned => "assigned to you",
+            Reason::Watchlist => "touches the watchlist",
+            Reason::RecentlyUpdated => "recently updated",
+            Reason::SmallDiff => "small diff",
+        })
+    }
+}
+
+/// What we know about an MR or a commit, as far as scoring goes
+#[derive(Debug, Default, Clone, Copy)]
+struct Signals {
+    assigned: bool,
+    watchlist: bool,
+    recently_updated: bool,
+    small_diff: bool,
+}
+
+impl Signals {
+    /// Most important first
+    fn reasons(self) -> Vec<Reason> {
+        [
+            (self.assigned, Reason::Assigned),
+            (self.watchlist, Reason::Watchlist),
+            (self.recently_updated, Reason::RecentlyUpdated),
+            (self.small_diff, Reason::SmallDiff),
+        ]
+        .iter()
+        .filter(|(x, _)| *x)
+        .map(|(_, reason)| *reason)
+        .collect()
+    }
+}
+
+/// An MR, or a single commit in one
+struct Item {
+    desc: String,
+    /// Most important first
+    reasons: Vec<Reason>,
+}
+
+impl Item {
+    fn score(&self) -> u32 {
+        self.reasons.iter().map(|x| x.weight()).sum()
+    }
+
+    fn primary_reason(&self) -> Option<Reason> {
+        self.reasons.first().copied()
+    }
+}
+
+/// Highest score first.  The sort is stable, so ties stay in the order they
+/// came in.
+fn rank(items: &mut [Item]) {
+    items.sort_by_key(|x| std::cmp::Reverse(x.score()));
+}
+
+/// Score every MR with unreviewed commits, and every unreviewed commit in
+/// those MRs, and print the `count` highest-scoring ones
+pub fn triage(repo: &Repository, count: usize) -> anyhow::Result<()> {
+    let me = config::get_string(&repo.config()?, "gitlab.username")?;
+    // Not having a watchlist is fine here; nothing matches it
+    let watchlist = load_watchlist(repo).unwrap_or_else(|_| GlobSet::empty());
+    let mut items = vec![];
+    for x in cached_open_mrs(repo)? {
+        if x.mr.author.username == me {
+            continue;
+        }
+        if let Err(e) = score_mr(repo, &me, &watchlist, &x, &mut items) {
+            error!("{}: {}", x.mr.iid.0, e);
+        }
+    }
+    if items.is_empty() {
+        println!("Everything looks good!");
+        return Ok(());
+    }
+    // Ties stay in order of most recently updated MR
+    rank(&mut items);
+
+    let mut tw = TabWriter::new(std::io::stdout()).ansi(true);
+    for item in items.iter().take(count) {
+        writeln!(
+            tw,
+            "{}\t{}\t{}",
+            item.score(),
+            item.desc,
+            item.primary_reason()
+                .map_or(String::new(), |x| x.to_string()),
+        )?;
+    }
+    tw.flush()?;
+    Ok(())
+}
+
+fn score_mr(
+    repo: &Repository,
+    me: &str,
+    watchlist: &GlobSet,
+    MRWithVersions { mr, versions }: &MRWithVersions,
+    items: &mut Vec<Item>,
+) -> anyhow::Result<()> {
+    let Some((_, latest)) = versions.last_key_value() else {
+        return Ok(());
+    };
+    let mut new = vec![];
+    for x in walk_version(repo, latest)? {
+        let (oid, status) = x?;
+        if status == Status::New {
+            new.push(oid);
+        }
+    }
+    if new.is_empty() {
+        return Ok(());
+    }
+
+    let recent = chrono::Duration::days(7);
+    let assigned = is_assigned(mr, me);
+    let base = repo.find_commit(latest.base.as_oid())?.tree()?;
+    let head = repo.find_commit(latest.head.as_oid())?.tree()?;
+    let signals = Signals {
+        assigned,
+        watchlist: mr_paths(repo, latest)?
+            .iter()
+            .any(|path| watchlist.is_match(path)),
+        recently_updated: chrono::Utc::now() - mr.updated_at < recent,
+        small_diff: n_changed(&repo.diff_tree_to_tree(Some(&base), Some(&head), None)?)?
+            <= SMALL_MR,
+    };
+    let mr_ref = fmt_mr_ref(repo, mr);
+    items.push(Item {
+        desc: format!("{} {} ({} unreviewed)", mr_ref, mr.title, new.len()),
+        reasons: signals.reasons(),
+    });
+
+    for oid in new {
+        let commit = repo.find_commit(oid)?;
+        let diff = commit_diff(repo, &commit)?;
+        let time = chrono::DateTime::from_timestamp(commit.time().seconds(), 0);
+        let signals = Signals {
+            assigned,
+            watchlist: diff
+                .deltas()
+                .filter_map(|x| x.new_file().path())
+                .any(|path| watchlist.is_match(path)),
+            recently_updated: time.is_some_and(|x| chrono::Utc::now() - x < recent),
+            small_diff: n_changed(&diff)? <= SMALL_COMMIT,
+        };
+        let short_id = commit.as_object().short_id()?;
+        items.push(Item {
+            desc: format!(
+                "{} {} {}",
+                mr_ref,
+                short_id.as_str().unwrap_or(""),
+                commit.summary().unwrap_or("")
+            ),
+            reasons: signals.reasons(),
+        });
+    }
+    Ok(())
+}
+
+/// Add up how much reviewing someone has waiting for them, and list the MRs
+/// which are theirs to look at.  The score is in arbitrary units:
+///
+///   4 × assigned MRs + 2 × watchlist MRs + MRs with unreviewed commits
+///     + unreviewed commits / 10
+///
+/// Only open MRs by other people which still have unreviewed commits count.
+/// An MR which is both assigned and on the watchlist only counts as
+/// assigned.
+pub fn review_load(repo: &Repository, reviewer: Option<String>) -> anyhow::Result<()> {
+    let reviewer = match reviewer {
+        Some(x) => x.trim_start_matches('@').to_owned(),
+        None => config::get_string(&repo.config()?, "gitlab.username")?,
+    };
+    let watchlist = load_watchlist(repo).unwrap_or_else(|_| GlobSet::empty());
+    let (mut n_assigned, mut n_watchlist, mut n_mrs, mut n_commits) = (0, 0, 0, 0);
+    let mut todo = vec![];
+    for MRWithVersions { mr, versions } in cached_open_mrs(repo)? {
+        if mr.author.username == reviewer {
+            continue;
+        }
+        let n_unreviewed = match mr_unreviewed_count(repo, &versions) {
+            Ok(x) => x,
+            Err(e) => {
+                error!("{}: {}", mr.iid.0, e);
+                continue;
+            }
+        };
+        if n_unreviewed == 0 {
+            continue;
+        }
+        n_mrs += 1;
+        n_commits += n_unreviewed;
+        let (_, latest) = versions.last_key_value().unwrap();
+        let reason = if is_assigned(&mr, &reviewer) {
+            n_assigned += 1;
+            Reason::Assigned
+        } else if mr_paths(repo, latest)?
+            .iter()
+            .any(|path| watchlist.is_match(path))
+        {
+            n_watchlist += 1;
+            Reason::Watchlist
+        } else {
+            continue;
+        };
+        let desc = format!(
+            "{} {} ({} unreviewed)",
+            fmt_mr_ref(repo, &mr),
+            mr.title,
+            n_unreviewed
+        );
+        todo.push((reason, desc));
+    }
+    let score = Reason::Assigned.weight() as usize * n_assigned
+        + Reason::Watchlist.weight() as usize * n_watchlist
+        + n_mrs
+        + n_commits / 10;
+
+    println!("Review load for @{}: {}", reviewer, score);
+    println!();
+    let mut tw = TabWriter::new(std::io::stdout()).ansi(true);
+    writeln!(tw, "  Assigned MRs:\t{}", n_assigned)?;
+    writeln!(tw, "  Watchlist MRs:\t{}", n_watchlist)?;
+    writeln!(tw, "  MRs with unreviewed commits:\t{}", n_mrs)?;
+    writeln!(tw, "  Unreviewed commits:\t{}", n_commits)?;
+    tw.flush()?;
+    if !todo.is_empty() {
+        println!();
+        println!("To do:");
+        println!();
+        // Stable, so assigned MRs stay in order of most recently updated
+        todo.sort_by_key(|(reason, _)| std::cmp::Reverse(reason.weight()));
+        let mut tw = TabWriter::new(std::io::stdout()).ansi(true);
+        for (reason, desc) in todo {
+            let why = match reason {
+                Reason::Assigned => "assigned",
+                _ => "touches the watchlist",
+            };
+            writeln!(tw, "  {}\t{}", desc, why)?;
+        }
+        tw.flush()?;
+    }
+    Ok(())
+}
+
+fn n_changed(diff: &Diff) -> anyhow::Result<usize> {
+    let stats = diff.stats()?;
+    Ok(stats.insertions() + stats.deletions())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(desc: &str, signals: Signals) -> Item {
+        Item {
+            desc: desc.into(),
+            reasons: signals.reasons(),
+        }
+    }
+
+    #[test]
+    fn weights() {
+        let all = Signals {
+            assigned: true,
+            watchlist: true,
+            recently_updated: true,
+            small_diff: true,
+        };
+        assert_eq!(item("", all).score(), 4 + 2 + 1 + 1);
+        assert_eq!(item("", Signals::default()).score(), 0);
+        let watched = Signals {
+            watchlist: true,
+            small_diff: true,
+            ..Signals::default()
+        };
+        assert_eq!(item("", watched).score(), 3);
+    }
+
+    #[test]
+    fn primary_reason_is_the_most_important() {
+        let x = Signals {
+            small_diff: true,
+            watchlist: true,
+            ..Signals::default()
+        };
+        assert_eq!(item("", x).primary_reason(), Some(Reason::Watchlist));
+        assert_eq!(item("", Signals::default()).primary_reason(), None);
+    }
+
+    #[test]
+    fn ranking() {
+        let recent = Signals {
+            recently_updated: true,
+            ..Signals::default()
+        };
+        let small = Signals {
+            small_diff: true,
+            ..Signals::default()
+        };
+        let assigned = Signals {
+            assigned: true,
+            ..Signals::default()
+        };
+        let mut items = vec![
+            item("a", recent),
+            item("b", assigned),
+            item("c", small),
+            item("d", Signals::default()),
+        ];
+        rank(&mut items);
+        let order = items.iter().map(|x| x.desc.as_str()).collect::<Vec<_>>();
+        // "a" and "c" are tied, so they keep their order
+        assert_eq!(order, ["b", "a", "c", "d"]);
+    }
+}
@@ -0,0 +1,212 @@
+use crate::render_note;
+use crate::review_db::*;
+use git2::{DiffFormat, Oid, Repository};
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+
+const HELP_LIST: &str = "↑/↓: select  enter: show diff  m: mark  s: skip  c: checkpoint  q: quit";
+const HELP_DIFF: &str = "j/k: scroll  m: mark  s: skip  c: checkpoint  esc: back  q: quit";
+
+struct App<'a> {
+    repo: &'a Repository,
+    range: Option<String>,
+    /// The unreviewed commits, oldest first, with their one-line summaries
+    commits: Vec<(Oid, String)>,
+    selected: ListState,
+    /// The diff of the selected commit, if we're looking at it
+    diff: Option<Vec<Line<'static>>>,
+    scroll: u16,
+    /// The result of the last action
+    message: String,
+}
+
+/// Browse the unreviewed commits, and mark them without leaving the terminal
+pub fn tui(repo: &Repository, range: Option<String>) -> anyhow::Result<()> {
+    let mut app = App {
+        repo,
+        range,
+        commits: vec![],
+        selected: ListState::default(),
+        diff: None,
+        scroll: 0,
+        message: String::new(),
+    };
+    app.refresh()?;
+    if app.commits.is_empty() {
+        println!("Everything looks good!");
+        return Ok(());
+    }
+    let mut terminal = ratatui::init();
+    let ret = app.run(&mut terminal);
+    ratatui::restore();
+    ret
+}
+
+impl App<'_> {
+    fn run(&mut self, terminal: &mut DefaultTerminal) -> anyhow::Result<()> {
+        loop {
+            terminal.draw(|frame| self.draw(frame))?;
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            match (key.code, self.diff.is_some()) {
+                (KeyCode::Char('q'), _) => return Ok(()),
+                (KeyCode::Esc, true) => self.diff = None,
+                (KeyCode::Esc, false) => return Ok(()),
+                (KeyCode::Up, false) => self.selected.select_previous(),
+                (KeyCode::Down, false) => self.selected.select_next(),
+                (KeyCode::Enter, false) => self.show_diff()?,
+                (KeyCode::Char('k') | KeyCode::Up, true) => {
+                    self.scroll = self.scroll.saturating_sub(1)
+                }
+                (KeyCode::Char('j') | KeyCode::Down, true) => {
+                    self.scroll = self.scroll.saturating_add(1)
+                }
+                (KeyCode::PageUp, true) => self.scroll = self.scroll.saturating_sub(20),
+                (KeyCode::PageDown, true) => self.scroll = self.scroll.saturating_add(20),
+                (KeyCode::Char('m'), _) => {
+                    self.mark(|repo, oid| render_note(repo, oid, "Reviewed"))?
+                }
+                (KeyCode::Char('s'), _) => {
+                    self.mark(|repo, oid| render_note(repo, oid, "Skipped"))?
+                }
+                (KeyCode::Char('c'), _) => self.mark(|_, _| Ok(checkpoint_note(None)))?,
+                _ => (),
+            }
+        }
+    }
+
+    /// Reload the list of unreviewed commits, trying to keep the same
+    /// position in the list
+    fn refresh(&mut self) -> anyhow::Result<()> {
+        let mut oids = vec![];
+        walk_new(self.repo, self.range.as_ref(), |oid| oids.push(oid))?;
+        oids.reverse();
+        self.commits = oids
+            .into_iter()
+            .map(|oid| {
+                let c = self.repo.find_commit(oid)?;
+                let short_id = c.as_object().short_id()?;
+                let line = format!(
+                    "{} {}",
+                    short_id.as_str().unwrap_or(""),
+                    c.summary().unwrap_or("")
+                );
+                Ok((oid, line))
+            })
+            .collect::<anyhow::Result<_>>()?;
+        let idx = self.selected.selected().unwrap_or(0);
+        self.selected.select(if self.commits.is_empty() {
+            None
+        } else {
+            Some(idx.min(self.commits.len() - 1))
+        });
+        Ok(())
+    }
+
+    fn current(&self) -> Option<Oid> {
+        self.commits.get(self.selected.selected()?).map(|x| x.0)
+    }
+
+    fn mark(
+        &mut self,
+        note: impl FnOnce(&Repository, Oid) -> anyhow::Result<String>,
+    ) -> anyhow::Result<()> {
+        let Some(oid) = self.current() else {
+            return Ok(());
+        };
+        let note = note(self.repo, oid)?;
+        self.message = format!("{}: {}", oid, merge_note(self.repo, oid, &note)?);
+        self.diff = None;
+        self.refresh()
+    }
+
+    fn show_diff(&mut self) -> anyhow::Result<()> {
+        let Some(oid) = self.current() else {
+            return Ok(());
+        };
+        let commit = self.repo.find_commit(oid)?;
+        let mut lines = vec![
+            Line::styled(format!("commit {}", oid), Style::new().fg(Color::Yellow)),
+            Line::raw(format!(
+                "Author: {} <{}>",
+                commit.author().name().unwrap_or(""),
+                commit.author().email().unwrap_or("")
+            )),
+            Line::raw(""),
+        ];
+        for line in commit.message().unwrap_or("").lines() {
+            lines.push(Line::raw(format!("    {}", line)));
+        }
+        lines.push(Line::raw(""));
+        let diff = commit_diff(self.repo, &commit)?;
+        diff.print(DiffFormat::Patch, |_, _, line| {
+            let content = String::from_utf8_lossy(line.content());
+            let content = content.trim_end_matches('\n');
+            let (prefix, style) = match line.origin() {
+                '+' => ("+", Style::new().fg(Color::Green)),
+                '-' => ("-", Style::new().fg(Color::Red)),
+                ' ' => (" ", Style::new()),
+                'H' => ("", Style::new().fg(Color::Cyan)),
+                'F' => ("", Style::new().add_modifier(Modifier::BOLD)),
+                _ => ("", Style::new()),
+            };
+            for x in content.split('\n') {
+                lines.push(Line::styled(format!("{}{}", prefix, x), style));
+            }
+            true
+        })?;
+        self.diff = Some(lines);
+        self.scroll = 0;
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let [main, status] =
+            Layout::vertical([Constraint::Min(1), Constraint::Length(1)]).areas(frame.area());
+        let help = if self.diff.is_some() {
+            HELP_DIFF
+        } else {
+            HELP_LIST
+        };
+        let status_line = if self.message.is_empty() {
+            Line::raw(help)
+        } else {
+            Line::from(vec![
+                Span::styled(&self.message, Style::new().fg(Color::Green)),
+                Span::raw("  "),
+                Span::raw(help),
+            ])
+        };
+        frame.render_widget(Paragraph::new(status_line), status);
+
+        if let Some(diff) = &self.diff {
+            let title = self
+                .current()
+                .map_or(String::new(), |oid| format!(" {} ", oid));
+            let para = Paragraph::new(diff.clone())
+                .block(Block::default().borders(Borders::ALL).title(title))
+                .scroll((self.scroll, 0));
+            frame.render_widget(para, main);
+            return;
+        }
+
+        let title = format!(" {} unreviewed commits ", self.commits.len());
+        let items = self
+            .commits
+            .iter()
+            .map(|(_, line)| ListItem::new(line.as_str()))
+            .collect::<Vec<_>>();
+        let list = List::new(items)
+            .block(Block::default().borders(Borders::ALL).title(title))
+            .highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, main, &mut self.selected);
+    }
+}